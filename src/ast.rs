@@ -1,8 +1,29 @@
-use crate::{environment::Environment, object::Object};
+use crate::{environment::Environment, object::ObjectRef};
 use std::fmt::{Debug, Error, Formatter};
 
 pub trait Node {
-    fn eval(&self, env: &mut Environment) -> Box<dyn Object>;
+    fn eval(&self, env: &mut Environment) -> ObjectRef;
+}
+
+// Where a node came from in the source: byte offsets of its start and end, and the 1-based
+// line it starts on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+}
+
+impl Span {
+    // NOTE: The line is left at 0 here and filled in by `parser::parse_program` once the whole
+    // input is parsed, so the source isn't rescanned for every node.
+    pub fn new(start: usize, end: usize) -> Self {
+        Span {
+            start,
+            end,
+            line: 0,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -12,32 +33,76 @@ pub struct Program {
 
 #[derive(Clone)]
 pub enum Stmt {
-    Let { name: String, value: Box<Expr> },
-    Return { return_value: Box<Expr> },
-    Expr { expression: Box<Expr> },
-    Block { statements: Vec<Box<Stmt>> },
+    Let {
+        name: String,
+        value: Box<Expr>,
+        span: Span,
+    },
+    LetDestructure {
+        names: Vec<String>,
+        value: Box<Expr>,
+        span: Span,
+    },
+    Const {
+        name: String,
+        value: Box<Expr>,
+        span: Span,
+    },
+    Assign {
+        name: String,
+        value: Box<Expr>,
+        span: Span,
+    },
+    Return {
+        return_value: Box<Expr>,
+    },
+    Expr {
+        expression: Box<Expr>,
+    },
+    Block {
+        statements: Vec<Box<Stmt>>,
+    },
+    While {
+        condition: Box<Expr>,
+        body: Box<Stmt>,
+    },
 }
 
 #[derive(Clone)]
 pub enum Expr {
     Number(i64),
-    Identifier(String),
+    Identifier(String, Span),
     Boolean(bool),
     StringLit(String),
     InfixOp {
         left: Box<Expr>,
         operator: Opcode,
         right: Box<Expr>,
+        span: Span,
     },
     PrefixOp {
         operator: Opcode,
         right: Box<Expr>,
+        span: Span,
     },
     If {
         condition: Box<Expr>,
         consequence: Box<Stmt>,
         alternative: Option<Box<Stmt>>,
     },
+    With {
+        bindings: Vec<(String, Box<Expr>)>,
+        body: Box<Stmt>,
+    },
+    Try {
+        body: Box<Stmt>,
+        cleanup: Box<Stmt>,
+    },
+    LetIn {
+        name: String,
+        value: Box<Expr>,
+        body: Box<Expr>,
+    },
     FuncLit {
         parameters: Vec<Box<Expr>>,
         body: Box<Stmt>,
@@ -45,6 +110,7 @@ pub enum Expr {
     Call {
         function: Box<Expr>,
         arguments: Vec<Box<Expr>>,
+        span: Span,
     },
     ArrayLit {
         elements: Vec<Box<Expr>>,
@@ -52,11 +118,12 @@ pub enum Expr {
     Index {
         left: Box<Expr>,
         index: Box<Expr>,
+        span: Span,
     },
     HashLit {
         pairs: Vec<(Box<Expr>, Box<Expr>)>,
-    }, // TODO:
-       // Hash Index Expression
+        span: Span,
+    },
 }
 
 #[derive(Copy, Clone)]
@@ -70,6 +137,59 @@ pub enum Opcode {
     NotEq,
     Lt,
     Gt,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+}
+
+impl Stmt {
+    pub fn span(&self) -> Option<Span> {
+        match *self {
+            Stmt::Let { span, .. }
+            | Stmt::LetDestructure { span, .. }
+            | Stmt::Const { span, .. }
+            | Stmt::Assign { span, .. } => Some(span),
+            _ => None,
+        }
+    }
+
+    pub fn span_mut(&mut self) -> Option<&mut Span> {
+        match self {
+            Stmt::Let { span, .. }
+            | Stmt::LetDestructure { span, .. }
+            | Stmt::Const { span, .. }
+            | Stmt::Assign { span, .. } => Some(span),
+            _ => None,
+        }
+    }
+}
+
+impl Expr {
+    pub fn span(&self) -> Option<Span> {
+        match *self {
+            Expr::Identifier(_, span)
+            | Expr::InfixOp { span, .. }
+            | Expr::PrefixOp { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Index { span, .. }
+            | Expr::HashLit { span, .. } => Some(span),
+            _ => None,
+        }
+    }
+
+    pub fn span_mut(&mut self) -> Option<&mut Span> {
+        match self {
+            Expr::Identifier(_, span)
+            | Expr::InfixOp { span, .. }
+            | Expr::PrefixOp { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Index { span, .. }
+            | Expr::HashLit { span, .. } => Some(span),
+            _ => None,
+        }
+    }
 }
 
 impl Debug for Stmt {
@@ -79,7 +199,23 @@ impl Debug for Stmt {
             Let {
                 ref name,
                 ref value,
+                ..
             } => write!(fmt, "let {} = {:?}", name, value),
+            LetDestructure {
+                ref names,
+                ref value,
+                ..
+            } => write!(fmt, "let [{}] = {:?}", names.join(", "), value),
+            Const {
+                ref name,
+                ref value,
+                ..
+            } => write!(fmt, "const {} = {:?}", name, value),
+            Assign {
+                ref name,
+                ref value,
+                ..
+            } => write!(fmt, "{} = {:?}", name, value),
             Return { ref return_value } => write!(fmt, "return {:?}", return_value),
             Expr { ref expression } => write!(fmt, "{:?}", expression),
             Block { ref statements } => {
@@ -89,6 +225,10 @@ impl Debug for Stmt {
                 }
                 write!(fmt, "}}")
             }
+            While {
+                ref condition,
+                ref body,
+            } => write!(fmt, "while ({:?}) {:?}", condition, body),
         }
     }
 }
@@ -98,17 +238,19 @@ impl Debug for Expr {
         use self::Expr::*;
         match *self {
             Number(n) => write!(fmt, "{:?}", n),
-            Identifier(ref s) => write!(fmt, "{}", s),
+            Identifier(ref s, _) => write!(fmt, "{}", s),
             Boolean(b) => write!(fmt, "{:?}", b),
-            StringLit(ref s) => write!(fmt, "{}", s),
+            StringLit(ref s) => write!(fmt, "{:?}", s),
             InfixOp {
                 ref left,
                 ref operator,
                 ref right,
+                ..
             } => write!(fmt, "({:?} {:?} {:?})", left, operator, right),
             PrefixOp {
                 ref operator,
                 ref right,
+                ..
             } => write!(fmt, "({:?}{:?})", operator, right),
             If {
                 ref condition,
@@ -118,11 +260,41 @@ impl Debug for Expr {
                 let mut s = String::new();
                 s.push_str(&format!("if ({:?}) ", condition));
                 s.push_str(&format!("{:?}", consequence));
-                if let Some(ref alt) = *alternative {
-                    s.push_str(&format!(" else {:?}", alt));
+                match alternative.as_deref() {
+                    // Written back the way `else if` sugar is parsed.
+                    Some(Stmt::Block { statements }) if is_lone_if(statements) => {
+                        s.push_str(&format!(" else {:?}", statements[0]));
+                    }
+                    Some(alt) => s.push_str(&format!(" else {:?}", alt)),
+                    None => {}
                 }
                 write!(fmt, "{}", s)
             }
+            With {
+                ref bindings,
+                ref body,
+            } => {
+                let mut s = String::new();
+                s.push_str("with (");
+                for (i, (name, value)) in bindings.iter().enumerate() {
+                    if i > 0 {
+                        s.push_str(", ");
+                    }
+                    s.push_str(&format!("{} = {:?}", name, value));
+                }
+                s.push_str(") ");
+                s.push_str(&format!("{:?}", body));
+                write!(fmt, "{}", s)
+            }
+            Try {
+                ref body,
+                ref cleanup,
+            } => write!(fmt, "try {:?} finally {:?}", body, cleanup),
+            LetIn {
+                ref name,
+                ref value,
+                ref body,
+            } => write!(fmt, "let {} = {:?} in {:?}", name, value, body),
             FuncLit {
                 ref parameters,
                 ref body,
@@ -142,6 +314,7 @@ impl Debug for Expr {
             Call {
                 ref function,
                 ref arguments,
+                ..
             } => {
                 let mut s = String::new();
                 s.push_str(&format!("{:?}(", function));
@@ -151,38 +324,48 @@ impl Debug for Expr {
                     }
                     s.push_str(&format!("{:?}", arg));
                 }
-                s.push_str(")");
+                s.push(')');
                 write!(fmt, "{}", s)
             }
             ArrayLit { ref elements } => {
                 let mut s = String::new();
-                s.push_str("[");
+                s.push('[');
                 for (i, e) in elements.iter().enumerate() {
                     if i > 0 {
                         s.push_str(", ");
                     }
                     s.push_str(&format!("{:?}", e));
                 }
-                s.push_str("]");
+                s.push(']');
                 write!(fmt, "{}", s)
             }
             Index {
                 ref left,
                 ref index,
+                ..
             } => write!(fmt, "({:?}[{:?}])", left, index),
-            HashLit { ref pairs } => {
+            HashLit { ref pairs, .. } => {
                 let mut s = String::new();
-                s.push_str("{");
+                s.push('{');
                 for (k, v) in pairs.iter() {
                     s.push_str(&format!("{:?}: {:?}, ", k, v));
                 }
-                s.push_str("}");
+                s.push('}');
                 write!(fmt, "{}", s)
             }
         }
     }
 }
 
+pub(crate) fn is_lone_if(statements: &[Box<Stmt>]) -> bool {
+    match statements {
+        [stmt] => {
+            matches!(**stmt, Stmt::Expr { ref expression } if matches!(**expression, Expr::If { .. }))
+        }
+        _ => false,
+    }
+}
+
 impl Debug for Opcode {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         use self::Opcode::*;
@@ -196,6 +379,11 @@ impl Debug for Opcode {
             NotEq => write!(fmt, "!="),
             Lt => write!(fmt, "<"),
             Gt => write!(fmt, ">"),
+            BitAnd => write!(fmt, "&"),
+            BitOr => write!(fmt, "|"),
+            BitXor => write!(fmt, "^"),
+            Shl => write!(fmt, "<<"),
+            Shr => write!(fmt, ">>"),
         }
     }
 }
@@ -213,12 +401,17 @@ impl Opcode {
             NotEq => "!=",
             Lt => "<",
             Gt => ">",
+            BitAnd => "&",
+            BitOr => "|",
+            BitXor => "^",
+            Shl => "<<",
+            Shr => ">>",
         }
     }
 }
 
-impl ToString for Stmt {
-    fn to_string(&self) -> String {
-        format!("{:?}", self)
+impl std::fmt::Display for Stmt {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(fmt, "{:?}", self)
     }
 }
@@ -1,99 +1,190 @@
-use crate::evaluator::new_error;
-use crate::object::{Array, Builtin, Integer, Null, ObjectRef, StringObj};
+use crate::evaluator::{
+    apply_function, eval_native_boolean, hash_key_of, is_error, is_truthy, new_error_of, null,
+    objects_equal,
+};
+use crate::json::{to_json, to_json_pretty};
+use crate::object::{
+    Array, Boolean, BoundFunction, Builtin, Error, ErrorKind, Function, Hash, HashPair, HashPairs,
+    Integer, ObjectRef, StringObj,
+};
 use crate::{box_it, downcast_ref};
+use indexmap::IndexMap;
 use lazy_static::lazy_static;
+use std::cell::Cell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// State of the splitmix64 generator behind the random builtins. It starts from the clock and is
+// reset by `seed(n)`, after which the sequence is reproducible.
+thread_local! {
+    static RNG_STATE: Cell<u64> = Cell::new(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default(),
+    );
+}
+
+fn next_random() -> u64 {
+    RNG_STATE.with(|state| {
+        let s = state.get().wrapping_add(0x9e3779b97f4a7c15);
+        state.set(s);
+        let mut z = s;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    })
+}
+
+// Moves `count` randomly chosen elements to the front, in random order (a partial Fisher-Yates).
+fn shuffle_prefix(elements: &mut [ObjectRef], count: usize) {
+    for i in 0..count {
+        let j = i + (next_random() % (elements.len() - i) as u64) as usize;
+        elements.swap(i, j);
+    }
+}
 
 fn len_builtin(args: Vec<ObjectRef>) -> ObjectRef {
     if args.len() != 1 {
-        return new_error(format_args!(
-            "wrong number of arguments. got={}, want=1",
-            args.len()
-        ));
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
     }
     if let Some(s) = downcast_ref!(args[0], StringObj) {
-        return box_it!(Integer {
+        box_it!(Integer {
             value: s.value.len() as i64
-        });
+        })
     } else if let Some(a) = downcast_ref!(args[0], Array) {
-        return box_it!(Integer {
+        box_it!(Integer {
             value: a.elements.len() as i64
-        });
+        })
     } else {
-        return new_error(format_args!(
-            "argument to `len` not supported, got {}",
-            args[0].object_type().as_str()
-        ));
+        new_error_of(
+            ErrorKind::Argument,
+            format_args!(
+                "argument to `len` not supported, got {}",
+                args[0].object_type().as_str()
+            ),
+        )
     }
 }
 
 fn first_builtin(args: Vec<ObjectRef>) -> ObjectRef {
     if args.len() != 1 {
-        return new_error(format_args!(
-            "wrong number of arguments. got={}, want=1",
-            args.len()
-        ));
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
     }
     if let Some(a) = downcast_ref!(args[0], Array) {
         if a.elements.is_empty() {
-            return box_it!(Null);
+            return null();
         }
         return a.elements[0].clone();
     }
-    return new_error(format_args!(
-        "argument to `first` must be ARRAY, got {}",
-        args[0].object_type().as_str()
-    ));
+    new_error_of(
+        ErrorKind::Argument,
+        format_args!(
+            "argument to `first` must be ARRAY, got {}",
+            args[0].object_type().as_str()
+        ),
+    )
 }
 
 fn last_builtin(args: Vec<ObjectRef>) -> ObjectRef {
     if args.len() != 1 {
-        return new_error(format_args!(
-            "wrong number of arguments. got={}, want=1",
-            args.len()
-        ));
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
     }
     if let Some(a) = downcast_ref!(args[0], Array) {
         if a.elements.is_empty() {
-            return box_it!(Null);
+            return null();
         }
         return a.elements[a.elements.len() - 1].clone();
     }
-    return new_error(format_args!(
-        "argument to `last` must be ARRAY, got {}",
-        args[0].object_type().as_str()
-    ));
+    new_error_of(
+        ErrorKind::Argument,
+        format_args!(
+            "argument to `last` must be ARRAY, got {}",
+            args[0].object_type().as_str()
+        ),
+    )
 }
 
 fn rest_builtin(args: Vec<ObjectRef>) -> ObjectRef {
     if args.len() != 1 {
-        return new_error(format_args!(
-            "wrong number of arguments. got={}, want=1",
-            args.len()
-        ));
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
     }
     if let Some(a) = downcast_ref!(args[0], Array) {
         if a.elements.is_empty() {
-            return box_it!(Null);
+            return null();
         }
         let new_elements = a.elements[1..].to_vec();
         return box_it!(Array {
             elements: new_elements
         });
     }
-    return new_error(format_args!(
-        "argument to `rest` must be ARRAY, got {}",
-        args[0].object_type().as_str()
-    ));
+    new_error_of(
+        ErrorKind::Argument,
+        format_args!(
+            "argument to `rest` must be ARRAY, got {}",
+            args[0].object_type().as_str()
+        ),
+    )
 }
 
-fn push_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+// Reads the array and element count arguments of `take` and `drop`. Counts past the end of the
+// array are clamped to its length.
+fn count_args<'a>(name: &str, args: &'a [ObjectRef]) -> Result<(&'a Array, usize), ObjectRef> {
     if args.len() != 2 {
-        return new_error(format_args!(
-            "wrong number of arguments. got={}, want=2",
-            args.len()
+        return Err(new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        ));
+    }
+    let array = expect_array(name, &args[0])?;
+    let count = expect_integer(name, &args[1])?;
+    if count < 0 {
+        return Err(new_error_of(
+            ErrorKind::Argument,
+            format_args!("count of `{}` must not be negative, got {}", name, count),
         ));
     }
+    Ok((array, array.elements.len().min(count as usize)))
+}
+
+fn take_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    match count_args("take", &args) {
+        Ok((array, count)) => box_it!(Array {
+            elements: array.elements[..count].to_vec()
+        }),
+        Err(e) => e,
+    }
+}
+
+fn drop_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    match count_args("drop", &args) {
+        Ok((array, count)) => box_it!(Array {
+            elements: array.elements[count..].to_vec()
+        }),
+        Err(e) => e,
+    }
+}
+
+fn push_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
     if let Some(a) = downcast_ref!(args[0], Array) {
         let mut new_elements = a.elements.clone();
         new_elements.push(args[1].clone());
@@ -101,25 +192,2012 @@ fn push_builtin(args: Vec<ObjectRef>) -> ObjectRef {
             elements: new_elements
         });
     }
-    return new_error(format_args!(
-        "argument to `push` must be ARRAY, got {}",
-        args[0].object_type().as_str()
-    ));
+    new_error_of(
+        ErrorKind::Argument,
+        format_args!(
+            "argument to `push` must be ARRAY, got {}",
+            args[0].object_type().as_str()
+        ),
+    )
 }
 
-lazy_static! {
-    pub static ref BUILTINS: HashMap<String, Builtin> = {
-        let mut builtins = HashMap::new();
-        builtins.insert("len".to_string(), Builtin { func: len_builtin });
+// Values are never shared: cloning an array or hash copies its elements all the way down, so the
+// copy is independent of the original. Functions still share the environment they close over.
+fn clone_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    args[0].clone()
+}
+
+fn expect_array<'a>(name: &str, arg: &'a ObjectRef) -> Result<&'a Array, ObjectRef> {
+    downcast_ref!(arg, Array).ok_or_else(|| {
+        new_error_of(
+            ErrorKind::Argument,
+            format_args!(
+                "argument to `{}` must be ARRAY, got {}",
+                name,
+                arg.object_type().as_str()
+            ),
+        )
+    })
+}
+
+fn expect_hash<'a>(name: &str, arg: &'a ObjectRef) -> Result<&'a Hash, ObjectRef> {
+    downcast_ref!(arg, Hash).ok_or_else(|| {
+        new_error_of(
+            ErrorKind::Argument,
+            format_args!(
+                "argument to `{}` must be HASH, got {}",
+                name,
+                arg.object_type().as_str()
+            ),
+        )
+    })
+}
+
+fn expect_integer(name: &str, arg: &ObjectRef) -> Result<i64, ObjectRef> {
+    downcast_ref!(arg, Integer).map(|i| i.value).ok_or_else(|| {
+        new_error_of(
+            ErrorKind::Argument,
+            format_args!(
+                "argument to `{}` must be INTEGER, got {}",
+                name,
+                arg.object_type().as_str()
+            ),
+        )
+    })
+}
+
+fn expect_function(name: &str, arg: &ObjectRef) -> Result<(), ObjectRef> {
+    if downcast_ref!(arg, Function).is_some()
+        || downcast_ref!(arg, Builtin).is_some()
+        || downcast_ref!(arg, BoundFunction).is_some()
+    {
+        return Ok(());
+    }
+    Err(new_error_of(
+        ErrorKind::Argument,
+        format_args!(
+            "argument to `{}` must be FUNCTION, got {}",
+            name,
+            arg.object_type().as_str()
+        ),
+    ))
+}
+
+#[cfg(feature = "io")]
+fn puts_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    for arg in &args {
+        println!("{}", arg.inspect());
+    }
+    null()
+}
+
+// NOTE: The sort is stable, so elements whose keys compare equal keep their input order.
+fn sort_by_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let array = match expect_array("sort_by", &args[0]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    if let Err(e) = expect_function("sort_by", &args[1]) {
+        return e;
+    }
+
+    let mut keyed = Vec::new();
+    for element in &array.elements {
+        let key = apply_function(args[1].clone(), std::slice::from_ref(element));
+        if is_error(&key) {
+            return key;
+        }
+        keyed.push((key, element.clone()));
+    }
+
+    if keyed
+        .iter()
+        .all(|(k, _)| downcast_ref!(k, Integer).is_some())
+    {
+        keyed.sort_by_key(|(k, _)| downcast_ref!(k, Integer).unwrap().value);
+    } else if keyed
+        .iter()
+        .all(|(k, _)| downcast_ref!(k, StringObj).is_some())
+    {
+        keyed.sort_by(|(a, _), (b, _)| {
+            let a = &downcast_ref!(a, StringObj).unwrap().value;
+            let b = &downcast_ref!(b, StringObj).unwrap().value;
+            a.cmp(b)
+        });
+    } else {
+        return new_error_of(
+            ErrorKind::Argument,
+            format_args!("sort keys must be all INTEGER or all STRING"),
+        );
+    }
+
+    box_it!(Array {
+        elements: keyed.into_iter().map(|(_, e)| e).collect()
+    })
+}
+
+// Integers sort numerically and strings lexicographically. A comparator `f(a, b)` can be given
+// instead, returning a negative, zero or positive integer as `a` sorts before, with or after `b`.
+fn sort_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.is_empty() || args.len() > 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1 or 2", args.len()),
+        );
+    }
+    let array = match expect_array("sort", &args[0]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+
+    if let Some(comparator) = args.get(1) {
+        if let Err(e) = expect_function("sort", comparator) {
+            return e;
+        }
+        let compare = |a: &ObjectRef, b: &ObjectRef| {
+            let result = apply_function(comparator.clone(), &[a.clone(), b.clone()]);
+            if is_error(&result) {
+                return Err(result);
+            }
+            match downcast_ref!(result, Integer) {
+                Some(n) => Ok(n.value.cmp(&0)),
+                None => Err(new_error_of(
+                    ErrorKind::Argument,
+                    format_args!(
+                        "comparator for `sort` must return INTEGER, got {}",
+                        result.object_type().as_str()
+                    ),
+                )),
+            }
+        };
+        return match merge_sort(array.elements.clone(), &compare) {
+            Ok(elements) => box_it!(Array { elements }),
+            Err(e) => e,
+        };
+    }
+
+    let mut elements = array.elements.clone();
+    if elements.iter().all(|e| downcast_ref!(e, Integer).is_some()) {
+        elements.sort_by_key(|e| downcast_ref!(e, Integer).unwrap().value);
+    } else if elements
+        .iter()
+        .all(|e| downcast_ref!(e, StringObj).is_some())
+    {
+        elements.sort_by(|a, b| {
+            let a = &downcast_ref!(a, StringObj).unwrap().value;
+            let b = &downcast_ref!(b, StringObj).unwrap().value;
+            a.cmp(b)
+        });
+    } else if elements
+        .iter()
+        .all(|e| e.object_type() == elements[0].object_type())
+    {
+        return new_error_of(
+            ErrorKind::Argument,
+            format_args!(
+                "cannot sort array of {}",
+                elements[0].object_type().as_str()
+            ),
+        );
+    } else {
+        return new_error_of(
+            ErrorKind::Argument,
+            format_args!("cannot sort array of mixed types"),
+        );
+    }
+    box_it!(Array { elements })
+}
+
+// A stable merge sort whose comparison can fail. Unlike `slice::sort_by`, it doesn't panic if the
+// comparison isn't a consistent total order, which a user-written comparator may not be.
+fn merge_sort<F>(mut elements: Vec<ObjectRef>, compare: &F) -> Result<Vec<ObjectRef>, ObjectRef>
+where
+    F: Fn(&ObjectRef, &ObjectRef) -> Result<Ordering, ObjectRef>,
+{
+    if elements.len() <= 1 {
+        return Ok(elements);
+    }
+    let right = merge_sort(elements.split_off(elements.len() / 2), compare)?;
+    let left = merge_sort(elements, compare)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut right = right.into_iter().peekable();
+    for l in left {
+        while let Some(r) = right.peek() {
+            if compare(r, &l)? == Ordering::Less {
+                merged.push(right.next().unwrap());
+            } else {
+                break;
+            }
+        }
+        merged.push(l);
+    }
+    merged.extend(right);
+    Ok(merged)
+}
+
+// NOTE: Upper- then lower-casing approximates Unicode case folding, so that e.g. "ß" and
+// "SS" compare equal.
+fn fold_case(s: &str) -> String {
+    s.to_uppercase().to_lowercase()
+}
+
+fn string_args<'a>(name: &str, args: &'a [ObjectRef]) -> Result<Vec<&'a StringObj>, ObjectRef> {
+    args.iter()
+        .map(|arg| {
+            downcast_ref!(arg, StringObj).ok_or_else(|| {
+                new_error_of(
+                    ErrorKind::Argument,
+                    format_args!(
+                        "argument to `{}` must be STRING, got {}",
+                        name,
+                        arg.object_type().as_str()
+                    ),
+                )
+            })
+        })
+        .collect()
+}
+
+fn to_upper_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    match string_args("to_upper", &args) {
+        Ok(strings) => box_it!(StringObj {
+            value: strings[0].value.to_uppercase()
+        }),
+        Err(e) => e,
+    }
+}
+
+fn to_lower_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    match string_args("to_lower", &args) {
+        Ok(strings) => box_it!(StringObj {
+            value: strings[0].value.to_lowercase()
+        }),
+        Err(e) => e,
+    }
+}
+
+// Trims whitespace from both ends, or with a second argument, any of the characters in it.
+fn trim_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 && args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1 or 2", args.len()),
+        );
+    }
+    let strings = match string_args("trim", &args) {
+        Ok(strings) => strings,
+        Err(e) => return e,
+    };
+    let value = match strings.get(1) {
+        Some(chars) => strings[0]
+            .value
+            .trim_matches(|c| chars.value.contains(c))
+            .to_string(),
+        None => strings[0].value.trim().to_string(),
+    };
+    box_it!(StringObj { value })
+}
+
+fn eq_ignore_case_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    match string_args("eq_ignore_case", &args) {
+        Ok(strings) => {
+            eval_native_boolean(&(fold_case(&strings[0].value) == fold_case(&strings[1].value)))
+        }
+        Err(e) => e,
+    }
+}
+
+// NOTE: An empty delimiter splits the string into its characters, rather than also yielding
+// the empty strings at either end like `str::split` would.
+fn split_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let strings = match string_args("split", &args) {
+        Ok(strings) => strings,
+        Err(e) => return e,
+    };
+    let (value, delimiter) = (&strings[0].value, &strings[1].value);
+    let parts: Vec<String> = if delimiter.is_empty() {
+        value.chars().map(String::from).collect()
+    } else {
+        value.split(delimiter.as_str()).map(String::from).collect()
+    };
+    box_it!(Array {
+        elements: parts
+            .into_iter()
+            .map(|value| -> ObjectRef { box_it!(StringObj { value }) })
+            .collect(),
+    })
+}
+
+// Like `split`, but stops after `n` parts, the last holding the rest of the string. A limit of 0
+// gives an empty array.
+fn split_n_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 3 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=3", args.len()),
+        );
+    }
+    let strings = match string_args("split_n", &args[..2]) {
+        Ok(strings) => strings,
+        Err(e) => return e,
+    };
+    let limit = match expect_integer("split_n", &args[2]) {
+        Ok(n) if n < 0 => {
+            return new_error_of(
+                ErrorKind::Argument,
+                format_args!("limit of `split_n` must not be negative, got {}", n),
+            )
+        }
+        Ok(n) => n as usize,
+        Err(e) => return e,
+    };
+    let (value, delimiter) = (&strings[0].value, &strings[1].value);
+    let parts: Vec<String> = if limit == 0 {
+        Vec::new()
+    } else if delimiter.is_empty() {
+        let mut parts: Vec<String> = Vec::new();
+        for (i, c) in value.char_indices() {
+            if parts.len() == limit - 1 {
+                parts.push(value[i..].to_string());
+                break;
+            }
+            parts.push(c.to_string());
+        }
+        parts
+    } else {
+        value
+            .splitn(limit, delimiter.as_str())
+            .map(String::from)
+            .collect()
+    };
+    box_it!(Array {
+        elements: parts
+            .into_iter()
+            .map(|value| -> ObjectRef { box_it!(StringObj { value }) })
+            .collect(),
+    })
+}
+
+fn join_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let array = match expect_array("join", &args[0]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    let separator = match string_args("join", &args[1..]) {
+        Ok(strings) => &strings[0].value,
+        Err(e) => return e,
+    };
+
+    let mut parts = Vec::with_capacity(array.elements.len());
+    for (i, element) in array.elements.iter().enumerate() {
+        match downcast_ref!(element, StringObj) {
+            Some(string) => parts.push(string.value.as_str()),
+            None => {
+                return new_error_of(
+                    ErrorKind::Argument,
+                    format_args!(
+                        "elements of `join` must be STRING, got {} at index {}",
+                        element.object_type().as_str(),
+                        i
+                    ),
+                )
+            }
+        }
+    }
+    box_it!(StringObj {
+        value: parts.join(separator),
+    })
+}
+
+fn contains_ignore_case_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    match string_args("contains_ignore_case", &args) {
+        Ok(strings) => eval_native_boolean(
+            &fold_case(&strings[0].value).contains(&fold_case(&strings[1].value)),
+        ),
+        Err(e) => e,
+    }
+}
+
+fn all_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    quantifier("all", args, false)
+}
+
+fn any_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    quantifier("any", args, true)
+}
+
+// Stops at the first element whose predicate result is `stop_on`, so `all` short-circuits on a
+// counterexample and `any` on an example.
+fn quantifier(name: &str, args: Vec<ObjectRef>, stop_on: bool) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let array = match expect_array(name, &args[0]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    if let Err(e) = expect_function(name, &args[1]) {
+        return e;
+    }
+
+    for element in &array.elements {
+        let result = apply_function(args[1].clone(), std::slice::from_ref(element));
+        if is_error(&result) {
+            return result;
+        }
+        if is_truthy(&result) == stop_on {
+            return eval_native_boolean(&stop_on);
+        }
+    }
+    eval_native_boolean(&!stop_on)
+}
+
+// Left-aligns every cell to the width of its column (in chars), separating columns with two
+// spaces. Rows may have different lengths.
+fn table_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    let rows = match expect_array("table", &args[0]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+
+    let mut cells: Vec<Vec<&str>> = Vec::new();
+    for row in &rows.elements {
+        let row = match downcast_ref!(row, Array) {
+            Some(r) => r,
+            None => {
+                return new_error_of(
+                    ErrorKind::Argument,
+                    format_args!(
+                        "table rows must be ARRAY, got {}",
+                        row.object_type().as_str()
+                    ),
+                )
+            }
+        };
+        match string_args("table", &row.elements) {
+            Ok(strings) => cells.push(strings.iter().map(|s| s.value.as_str()).collect()),
+            Err(e) => return e,
+        }
+    }
+
+    let mut widths: Vec<usize> = Vec::new();
+    for row in &cells {
+        for (i, cell) in row.iter().enumerate() {
+            let width = cell.chars().count();
+            if i == widths.len() {
+                widths.push(width);
+            } else if widths[i] < width {
+                widths[i] = width;
+            }
+        }
+    }
+
+    let lines: Vec<String> = cells
+        .iter()
+        .map(|row| {
+            let padded: Vec<String> = row
+                .iter()
+                .enumerate()
+                .map(|(i, cell)| {
+                    let padding = widths[i] - cell.chars().count();
+                    format!("{}{}", cell, " ".repeat(padding))
+                })
+                .collect();
+            padded.join("  ").trim_end().to_string()
+        })
+        .collect();
+
+    box_it!(StringObj {
+        value: lines.join("\n")
+    })
+}
+
+fn type_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    box_it!(StringObj {
+        value: args[0].object_type().as_str().to_string()
+    })
+}
+
+// Strings convert to themselves rather than to their quoted inspect form, so that
+// `"count: " + str(5)` and `str("a")` both read naturally.
+fn str_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    if let Some(s) = downcast_ref!(args[0], StringObj) {
+        return box_it!(s.clone());
+    }
+    box_it!(StringObj {
+        value: args[0].inspect()
+    })
+}
+
+fn filter_hash_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let hash = match expect_hash("filter_hash", &args[0]) {
+        Ok(h) => h,
+        Err(e) => return e,
+    };
+    if let Err(e) = expect_function("filter_hash", &args[1]) {
+        return e;
+    }
+
+    let mut pairs = HashPairs::default();
+    for (hash_key, pair) in &hash.pairs {
+        let keep = apply_function(args[1].clone(), &[pair.key.clone(), pair.value.clone()]);
+        if is_error(&keep) {
+            return keep;
+        }
+        if is_truthy(&keep) {
+            pairs.insert(hash_key.clone(), pair.clone());
+        }
+    }
+    box_it!(Hash { pairs })
+}
+
+fn int_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    if let Some(s) = downcast_ref!(args[0], StringObj) {
+        match s.value.parse::<i64>() {
+            Ok(value) => box_it!(Integer { value }),
+            Err(_) => new_error_of(
+                ErrorKind::Argument,
+                format_args!("could not parse {:?} as INTEGER", s.value),
+            ),
+        }
+    } else if downcast_ref!(args[0], Integer).is_some() {
+        args[0].clone()
+    } else {
+        new_error_of(
+            ErrorKind::Argument,
+            format_args!(
+                "argument to `int` not supported, got {}",
+                args[0].object_type().as_str()
+            ),
+        )
+    }
+}
+
+// `keys` and `values` both follow the hash's insertion order.
+fn keys_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    match expect_hash("keys", &args[0]) {
+        Ok(hash) => box_it!(Array {
+            elements: hash.pairs.values().map(|pair| pair.key.clone()).collect(),
+        }),
+        Err(e) => e,
+    }
+}
+
+fn values_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    match expect_hash("values", &args[0]) {
+        Ok(hash) => box_it!(Array {
+            elements: hash.pairs.values().map(|pair| pair.value.clone()).collect(),
+        }),
+        Err(e) => e,
+    }
+}
+
+fn delete_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let hash = match expect_hash("delete", &args[0]) {
+        Ok(h) => h,
+        Err(e) => return e,
+    };
+    let key = match hash_key_of(&args[1]) {
+        Ok(key) => key,
+        Err(e) => return e,
+    };
+
+    let mut pairs = hash.pairs.clone();
+    pairs.shift_remove(&key);
+    box_it!(Hash { pairs })
+}
+
+// Setting an existing key replaces its value in place, so the pair keeps its position.
+fn set_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 3 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=3", args.len()),
+        );
+    }
+    let hash = match expect_hash("set", &args[0]) {
+        Ok(h) => h,
+        Err(e) => return e,
+    };
+    let key = match hash_key_of(&args[1]) {
+        Ok(key) => key,
+        Err(e) => return e,
+    };
+
+    let mut pairs = hash.pairs.clone();
+    pairs.insert(
+        key,
+        HashPair {
+            key: args[1].clone(),
+            value: args[2].clone(),
+        },
+    );
+    box_it!(Hash { pairs })
+}
+
+// Appends to the array stored under a key, so a hash can hold several values per key.
+fn add_multi_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 3 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=3", args.len()),
+        );
+    }
+    let hash = match expect_hash("add_multi", &args[0]) {
+        Ok(h) => h,
+        Err(e) => return e,
+    };
+    let key = match hash_key_of(&args[1]) {
+        Ok(key) => key,
+        Err(e) => return e,
+    };
+
+    let mut elements = match hash.pairs.get(&key) {
+        Some(pair) => match downcast_ref!(pair.value, Array) {
+            Some(array) => array.elements.clone(),
+            None => {
+                return new_error_of(
+                    ErrorKind::Argument,
+                    format_args!(
+                        "values in `add_multi` must be ARRAY, got {} at key {}",
+                        pair.value.object_type().as_str(),
+                        args[1].inspect()
+                    ),
+                )
+            }
+        },
+        None => Vec::new(),
+    };
+    elements.push(args[2].clone());
+
+    let mut pairs = hash.pairs.clone();
+    pairs.insert(
+        key,
+        HashPair {
+            key: args[1].clone(),
+            value: box_it!(Array { elements }),
+        },
+    );
+    box_it!(Hash { pairs })
+}
+
+// `range(start, end)` counts up by 1, stopping before `end`. An explicit step must move from
+// `start` towards `end`, so `range(5, 0, -1)` counts down.
+fn range_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 && args.len() != 3 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2 or 3", args.len()),
+        );
+    }
+    let mut bounds = Vec::with_capacity(3);
+    for arg in &args {
+        match expect_integer("range", arg) {
+            Ok(n) => bounds.push(n),
+            Err(e) => return e,
+        }
+    }
+    let (start, end) = (bounds[0], bounds[1]);
+    let step = bounds.get(2).copied().unwrap_or(1);
+    if step == 0 {
+        return new_error_of(
+            ErrorKind::Argument,
+            format_args!("step for `range` must not be 0"),
+        );
+    }
+    if args.len() == 3 && start < end && step < 0 {
+        return new_error_of(
+            ErrorKind::Argument,
+            format_args!(
+                "step for `range` must be positive when counting up, got {}",
+                step
+            ),
+        );
+    }
+    if args.len() == 3 && start > end && step > 0 {
+        return new_error_of(
+            ErrorKind::Argument,
+            format_args!(
+                "step for `range` must be negative when counting down, got {}",
+                step
+            ),
+        );
+    }
+
+    let mut elements: Vec<ObjectRef> = Vec::new();
+    let mut n = start;
+    while (step > 0 && n < end) || (step < 0 && n > end) {
+        elements.push(box_it!(Integer { value: n }));
+        n = match n.checked_add(step) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    box_it!(Array { elements })
+}
+
+// An error with the given message, so scripts can fail in a way `error_kind` tells apart from
+// the interpreter's own errors.
+fn error_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    match string_args("error", &args) {
+        Ok(strings) => new_error_of(ErrorKind::User, format_args!("{}", strings[0].value)),
+        Err(e) => e,
+    }
+}
+
+// The kind of an error as a string, e.g. "TYPE_MISMATCH", or null for any other value. Its
+// argument is passed in even when it's an error, see `Builtin::takes_errors`.
+fn error_kind_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    match downcast_ref!(args[0], Error) {
+        Some(error) => box_it!(StringObj {
+            value: error.kind.as_str().to_string(),
+        }),
+        None => null(),
+    }
+}
+
+fn seed_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    let seed = match expect_integer("seed", &args[0]) {
+        Ok(n) => n,
+        Err(e) => return e,
+    };
+    RNG_STATE.with(|state| state.set(seed as u64));
+    null()
+}
+
+fn shuffle_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    let array = match expect_array("shuffle", &args[0]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    let mut elements = array.elements.clone();
+    let len = elements.len();
+    shuffle_prefix(&mut elements, len);
+    box_it!(Array { elements })
+}
+
+// `sample(a, n)` picks `n` elements at distinct positions of `a`, in random order.
+fn sample_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let array = match expect_array("sample", &args[0]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    let count = match expect_integer("sample", &args[1]) {
+        Ok(n) => n,
+        Err(e) => return e,
+    };
+    if count < 0 || count as usize > array.elements.len() {
+        return new_error_of(
+            ErrorKind::Argument,
+            format_args!(
+                "cannot sample {} elements from an array of length {}",
+                count,
+                array.elements.len()
+            ),
+        );
+    }
+    let mut elements = array.elements.clone();
+    shuffle_prefix(&mut elements, count as usize);
+    elements.truncate(count as usize);
+    box_it!(Array { elements })
+}
+
+// Calls the zero-argument `args[1]` `args[0]` times and returns the mean wall-clock time per
+// call in nanoseconds. An error from the thunk stops the run and is returned instead.
+fn benchmark_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let iterations = match downcast_ref!(args[0], Integer) {
+        Some(i) if i.value > 0 => i.value,
+        Some(i) => {
+            return new_error_of(
+                ErrorKind::Argument,
+                format_args!(
+                    "iteration count for `benchmark` must be positive, got {}",
+                    i.value
+                ),
+            )
+        }
+        None => {
+            return new_error_of(
+                ErrorKind::Argument,
+                format_args!(
+                    "argument to `benchmark` must be INTEGER, got {}",
+                    args[0].object_type().as_str()
+                ),
+            )
+        }
+    };
+    if let Err(e) = expect_function("benchmark", &args[1]) {
+        return e;
+    }
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        let result = apply_function(args[1].clone(), &[]);
+        if is_error(&result) {
+            return result;
+        }
+    }
+    let elapsed = start.elapsed().as_nanos() / iterations as u128;
+    box_it!(Integer {
+        value: i64::try_from(elapsed).unwrap_or(i64::MAX)
+    })
+}
+
+// Decimal digits of the absolute value, most significant first.
+fn decimal_digits(n: i64) -> Vec<i64> {
+    n.unsigned_abs()
+        .to_string()
+        .bytes()
+        .map(|b| (b - b'0') as i64)
+        .collect()
+}
+
+fn digits_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    match expect_integer("digits", &args[0]) {
+        Ok(n) => box_it!(Array {
+            elements: decimal_digits(n)
+                .into_iter()
+                .map(|value| -> ObjectRef { box_it!(Integer { value }) })
+                .collect()
+        }),
+        Err(e) => e,
+    }
+}
+
+fn abs_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    match expect_integer("abs", &args[0]) {
+        Ok(n) => match n.checked_abs() {
+            Some(value) => box_it!(Integer { value }),
+            None => new_error_of(
+                ErrorKind::Argument,
+                format_args!("integer overflow: abs({})", n),
+            ),
+        },
+        Err(e) => e,
+    }
+}
+
+fn pow_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let (base, exp) = match (
+        expect_integer("pow", &args[0]),
+        expect_integer("pow", &args[1]),
+    ) {
+        (Ok(base), Ok(exp)) => (base, exp),
+        (Err(e), _) | (_, Err(e)) => return e,
+    };
+    if exp < 0 {
+        return new_error_of(
+            ErrorKind::Argument,
+            format_args!("exponent of `pow` must not be negative, got {}", exp),
+        );
+    }
+    // These never overflow, however large the exponent.
+    let value = match base {
+        0 if exp > 0 => Some(0),
+        1 => Some(1),
+        -1 => Some(if exp % 2 == 0 { 1 } else { -1 }),
+        _ => u32::try_from(exp)
+            .ok()
+            .and_then(|exp| base.checked_pow(exp)),
+    };
+    match value {
+        Some(value) => box_it!(Integer { value }),
+        None => new_error_of(
+            ErrorKind::Argument,
+            format_args!("integer overflow: pow({}, {})", base, exp),
+        ),
+    }
+}
+
+// NOTE: The bit builtins see integers as 64-bit two's complement, so e.g. `popcount(-1)` is 64
+// and bit 63 is the sign bit.
+fn popcount_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    match expect_integer("popcount", &args[0]) {
+        Ok(n) => box_it!(Integer {
+            value: n.count_ones() as i64
+        }),
+        Err(e) => e,
+    }
+}
+
+// Reads the integer and bit position arguments of `bit` and `set_bit`.
+fn bit_args(name: &str, args: &[ObjectRef]) -> Result<(i64, u32), ObjectRef> {
+    if args.len() != 2 {
+        return Err(new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        ));
+    }
+    let n = expect_integer(name, &args[0])?;
+    let position = expect_integer(name, &args[1])?;
+    if !(0..64).contains(&position) {
+        return Err(new_error_of(
+            ErrorKind::Argument,
+            format_args!(
+                "bit position of `{}` must be between 0 and 63, got {}",
+                name, position
+            ),
+        ));
+    }
+    Ok((n, position as u32))
+}
+
+fn bit_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    match bit_args("bit", &args) {
+        Ok((n, position)) => eval_native_boolean(&(n & (1 << position) != 0)),
+        Err(e) => e,
+    }
+}
+
+fn set_bit_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    match bit_args("set_bit", &args) {
+        Ok((n, position)) => box_it!(Integer {
+            value: n | (1 << position)
+        }),
+        Err(e) => e,
+    }
+}
+
+// The smallest (or, with `Ordering::Greater`, the largest) of two or more integers.
+fn extreme_of(name: &str, args: &[ObjectRef], wanted: Ordering) -> ObjectRef {
+    if args.len() < 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!(
+                "wrong number of arguments. got={}, want=2 or more",
+                args.len()
+            ),
+        );
+    }
+    let mut result = None;
+    for arg in args {
+        match expect_integer(name, arg) {
+            Ok(n) => match result {
+                Some(r) if n.cmp(&r) != wanted => {}
+                _ => result = Some(n),
+            },
+            Err(e) => return e,
+        }
+    }
+    box_it!(Integer {
+        value: result.unwrap()
+    })
+}
+
+fn min_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    extreme_of("min", &args, Ordering::Less)
+}
+
+fn max_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    extreme_of("max", &args, Ordering::Greater)
+}
+
+// Groups the digits in threes, e.g. `format_number(-1234567)` is "-1,234,567". The separator
+// defaults to ",".
+fn format_number_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.is_empty() || args.len() > 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1 or 2", args.len()),
+        );
+    }
+    let n = match expect_integer("format_number", &args[0]) {
+        Ok(n) => n,
+        Err(e) => return e,
+    };
+    let separator = match string_args("format_number", &args[1..]) {
+        Ok(strings) => strings.first().map_or(",", |s| s.value.as_str()),
+        Err(e) => return e,
+    };
+
+    // `unsigned_abs` keeps `i64::MIN` from overflowing.
+    let digits = n.unsigned_abs().to_string();
+    let mut value = String::new();
+    if n < 0 {
+        value.push('-');
+    }
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            value.push_str(separator);
+        }
+        value.push(digit);
+    }
+    box_it!(StringObj { value })
+}
+
+fn digit_sum_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    match expect_integer("digit_sum", &args[0]) {
+        Ok(n) => box_it!(Integer {
+            value: decimal_digits(n).into_iter().sum()
+        }),
+        Err(e) => e,
+    }
+}
+
+// Numbers below 2, negatives included, are not prime.
+fn is_prime_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    let n = match expect_integer("is_prime", &args[0]) {
+        Ok(n) => n,
+        Err(e) => return e,
+    };
+    let value = n >= 2 && (2..).take_while(|d| *d <= n / d).all(|d| n % d != 0);
+    eval_native_boolean(&value)
+}
+
+// Returns every positive divisor of `|n|` in ascending order, e.g. `factors(12)` is
+// `[1, 2, 3, 4, 6, 12]`. Every integer divides 0, so `factors(0)` is an error.
+fn factors_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    let n = match expect_integer("factors", &args[0]) {
+        Ok(0) => {
+            return new_error_of(
+                ErrorKind::Argument,
+                format_args!("argument to `factors` must not be 0"),
+            )
+        }
+        Ok(n) => n.unsigned_abs(),
+        Err(e) => return e,
+    };
+
+    let mut small = Vec::new();
+    let mut large = Vec::new();
+    let mut d: u64 = 1;
+    while d * d <= n {
+        if n % d == 0 {
+            small.push(d);
+            if d != n / d {
+                large.push(n / d);
+            }
+        }
+        d += 1;
+    }
+    small.extend(large.into_iter().rev());
+    box_it!(Array {
+        elements: small
+            .into_iter()
+            .map(|d| -> ObjectRef { box_it!(Integer { value: d as i64 }) })
+            .collect()
+    })
+}
+
+// The `index`th Unicode scalar of a string, or `None` when the index is out of range.
+fn char_arg(name: &str, args: &[ObjectRef]) -> Result<Option<char>, ObjectRef> {
+    if args.len() != 2 {
+        return Err(new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        ));
+    }
+    let string = string_args(name, &args[..1])?[0];
+    let index = expect_integer(name, &args[1])?;
+    if index < 0 {
+        return Ok(None);
+    }
+    Ok(string.value.chars().nth(index as usize))
+}
+
+fn char_at_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    match char_arg("char_at", &args) {
+        Ok(Some(c)) => box_it!(StringObj {
+            value: c.to_string(),
+        }),
+        Ok(None) => null(),
+        Err(e) => e,
+    }
+}
+
+fn code_at_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    match char_arg("code_at", &args) {
+        Ok(Some(c)) => box_it!(Integer {
+            value: c as u32 as i64,
+        }),
+        Ok(None) => null(),
+        Err(e) => e,
+    }
+}
+
+// The position of the first element equal to `value` (as by `==`), or -1 if there is none.
+fn index_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let array = match expect_array("index", &args[0]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    let position = array
+        .elements
+        .iter()
+        .position(|element| objects_equal(element, &args[1]));
+    box_it!(Integer {
+        value: position.map_or(-1, |i| i as i64),
+    })
+}
+
+// The position of `target` in an array of integers sorted in ascending order, or -1 if it's not
+// there. On an unsorted array the result is meaningless, but still an index or -1.
+fn binary_search_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let array = match expect_array("binary_search", &args[0]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    let target = match expect_integer("binary_search", &args[1]) {
+        Ok(n) => n,
+        Err(e) => return e,
+    };
+    let mut values = Vec::with_capacity(array.elements.len());
+    for (i, element) in array.elements.iter().enumerate() {
+        match downcast_ref!(element, Integer) {
+            Some(integer) => values.push(integer.value),
+            None => {
+                return new_error_of(
+                    ErrorKind::Argument,
+                    format_args!(
+                        "elements of `binary_search` must be INTEGER, got {} at index {}",
+                        element.object_type().as_str(),
+                        i
+                    ),
+                )
+            }
+        }
+    }
+
+    box_it!(Integer {
+        value: values.binary_search(&target).map_or(-1, |i| i as i64),
+    })
+}
+
+// Substring search in a string, membership (as by `==`) in an array, and key presence in a hash.
+fn contains_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let found = if let Some(string) = downcast_ref!(args[0], StringObj) {
+        match string_args("contains", &args[1..]) {
+            Ok(needle) => string.value.contains(needle[0].value.as_str()),
+            Err(e) => return e,
+        }
+    } else if let Some(array) = downcast_ref!(args[0], Array) {
+        array.elements.iter().any(|e| objects_equal(e, &args[1]))
+    } else if let Some(hash) = downcast_ref!(args[0], Hash) {
+        match hash_key_of(&args[1]) {
+            Ok(key) => hash.pairs.contains_key(&key),
+            Err(e) => return e,
+        }
+    } else {
+        return new_error_of(
+            ErrorKind::Argument,
+            format_args!(
+                "argument to `contains` must be ARRAY, STRING or HASH, got {}",
+                args[0].object_type().as_str()
+            ),
+        );
+    };
+    eval_native_boolean(&found)
+}
+
+// Splits elements into runs of consecutive equal (as by `==`) elements.
+fn consecutive_runs(elements: &[ObjectRef]) -> Vec<Vec<ObjectRef>> {
+    let mut runs: Vec<Vec<ObjectRef>> = Vec::new();
+    for element in elements {
+        match runs.last_mut() {
+            Some(run) if objects_equal(&run[0], element) => run.push(element.clone()),
+            _ => runs.push(vec![element.clone()]),
+        }
+    }
+    runs
+}
+
+fn group_consecutive_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    match expect_array("group_consecutive", &args[0]) {
+        Ok(array) => box_it!(Array {
+            elements: consecutive_runs(&array.elements)
+                .into_iter()
+                .map(|elements| -> ObjectRef { box_it!(Array { elements }) })
+                .collect(),
+        }),
+        Err(e) => e,
+    }
+}
+
+fn transpose_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    let matrix = match expect_array("transpose", &args[0]) {
+        Ok(array) => array,
+        Err(e) => return e,
+    };
+    let mut rows: Vec<&Array> = Vec::with_capacity(matrix.elements.len());
+    for (i, row) in matrix.elements.iter().enumerate() {
+        let row = match downcast_ref!(row, Array) {
+            Some(row) => row,
+            None => {
+                return new_error_of(
+                    ErrorKind::Argument,
+                    format_args!(
+                        "rows of `transpose` must be ARRAY, got {} at index {}",
+                        row.object_type().as_str(),
+                        i
+                    ),
+                )
+            }
+        };
+        let width = rows
+            .first()
+            .map_or(row.elements.len(), |first| first.elements.len());
+        if row.elements.len() != width {
+            return new_error_of(
+                ErrorKind::Argument,
+                format_args!(
+                    "rows of `transpose` must all have length {}, got {} at index {}",
+                    width,
+                    row.elements.len(),
+                    i
+                ),
+            );
+        }
+        rows.push(row);
+    }
+
+    let width = rows.first().map_or(0, |row| row.elements.len());
+    box_it!(Array {
+        elements: (0..width)
+            .map(|j| -> ObjectRef {
+                box_it!(Array {
+                    elements: rows.iter().map(|row| row.elements[j].clone()).collect(),
+                })
+            })
+            .collect(),
+    })
+}
+
+fn rle_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    match expect_array("rle", &args[0]) {
+        Ok(array) => box_it!(Array {
+            elements: consecutive_runs(&array.elements)
+                .into_iter()
+                .map(|run| -> ObjectRef {
+                    box_it!(Array {
+                        elements: vec![
+                            run[0].clone(),
+                            box_it!(Integer {
+                                value: run.len() as i64
+                            }),
+                        ],
+                    })
+                })
+                .collect(),
+        }),
+        Err(e) => e,
+    }
+}
+
+// The most elements `rld` will produce, so a huge count is an error instead of an allocation
+// that aborts the process.
+const MAX_RLD_LENGTH: usize = 1 << 24;
+
+// Reads one `[value, count]` pair of an `rld` argument.
+fn rld_pair(index: usize, pair: &ObjectRef) -> Result<(&ObjectRef, usize), ObjectRef> {
+    let shape_error = || {
+        new_error_of(
+            ErrorKind::Argument,
+            format_args!(
+                "elements of `rld` must be [value, count] pairs, got {} at index {}",
+                pair.inspect(),
+                index
+            ),
+        )
+    };
+    let elements = match downcast_ref!(pair, Array) {
+        Some(array) if array.elements.len() == 2 => &array.elements,
+        _ => return Err(shape_error()),
+    };
+    match downcast_ref!(&elements[1], Integer) {
+        Some(count) if count.value >= 0 => Ok((&elements[0], count.value as usize)),
+        Some(count) => Err(new_error_of(
+            ErrorKind::Argument,
+            format_args!(
+                "count in `rld` must not be negative, got {} at index {}",
+                count.value, index
+            ),
+        )),
+        None => Err(shape_error()),
+    }
+}
+
+fn rld_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    let array = match expect_array("rld", &args[0]) {
+        Ok(array) => array,
+        Err(e) => return e,
+    };
+    let mut runs = Vec::new();
+    let mut length: usize = 0;
+    for (i, pair) in array.elements.iter().enumerate() {
+        let (value, count) = match rld_pair(i, pair) {
+            Ok(run) => run,
+            Err(e) => return e,
+        };
+        length = match length.checked_add(count) {
+            Some(length) if length <= MAX_RLD_LENGTH => length,
+            _ => {
+                return new_error_of(
+                    ErrorKind::Argument,
+                    format_args!("count in `rld` is too large, got {} at index {}", count, i),
+                )
+            }
+        };
+        runs.push((value, count));
+    }
+    let mut elements = Vec::with_capacity(length);
+    for (value, count) in runs {
+        elements.extend(std::iter::repeat_n(value, count).cloned());
+    }
+    box_it!(Array { elements })
+}
+
+fn reverse_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 1 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1", args.len()),
+        );
+    }
+    if let Some(array) = downcast_ref!(args[0], Array) {
+        box_it!(Array {
+            elements: array.elements.iter().rev().cloned().collect(),
+        })
+    } else if let Some(string) = downcast_ref!(args[0], StringObj) {
+        box_it!(StringObj {
+            value: string.value.chars().rev().collect(),
+        })
+    } else {
+        new_error_of(
+            ErrorKind::Argument,
+            format_args!(
+                "argument to `reverse` must be ARRAY or STRING, got {}",
+                args[0].object_type().as_str()
+            ),
+        )
+    }
+}
+
+// Resolves `slice` bounds against a length: negative bounds count back from the end, and both are
+// clamped to the sequence, with an end before the start giving an empty range.
+fn slice_range(len: usize, start: i64, end: i64) -> (usize, usize) {
+    let resolve = |bound: i64| {
+        let bound = if bound < 0 { bound + len as i64 } else { bound };
+        bound.clamp(0, len as i64) as usize
+    };
+    let (start, end) = (resolve(start), resolve(end));
+    (start, end.max(start))
+}
+
+fn slice_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 3 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=3", args.len()),
+        );
+    }
+    let mut bounds = [0; 2];
+    for (bound, arg) in bounds.iter_mut().zip(&args[1..]) {
+        match expect_integer("slice", arg) {
+            Ok(n) => *bound = n,
+            Err(e) => return e,
+        }
+    }
+
+    if let Some(array) = downcast_ref!(args[0], Array) {
+        let (start, end) = slice_range(array.elements.len(), bounds[0], bounds[1]);
+        box_it!(Array {
+            elements: array.elements[start..end].to_vec(),
+        })
+    } else if let Some(string) = downcast_ref!(args[0], StringObj) {
+        let (start, end) = slice_range(string.value.chars().count(), bounds[0], bounds[1]);
+        box_it!(StringObj {
+            value: string.value.chars().skip(start).take(end - start).collect(),
+        })
+    } else {
+        new_error_of(
+            ErrorKind::Argument,
+            format_args!(
+                "argument to `slice` must be ARRAY or STRING, got {}",
+                args[0].object_type().as_str()
+            ),
+        )
+    }
+}
+
+fn map_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let array = match expect_array("map", &args[0]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    if let Err(e) = expect_function("map", &args[1]) {
+        return e;
+    }
+
+    let mut elements = Vec::with_capacity(array.elements.len());
+    for element in &array.elements {
+        let result = apply_function(args[1].clone(), std::slice::from_ref(element));
+        if is_error(&result) {
+            return result;
+        }
+        elements.push(result);
+    }
+    box_it!(Array { elements })
+}
+
+fn filter_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let array = match expect_array("filter", &args[0]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    if let Err(e) = expect_function("filter", &args[1]) {
+        return e;
+    }
+
+    let mut elements = Vec::new();
+    for element in &array.elements {
+        let keep = apply_function(args[1].clone(), std::slice::from_ref(element));
+        if is_error(&keep) {
+            return keep;
+        }
+        if is_truthy(&keep) {
+            elements.push(element.clone());
+        }
+    }
+    box_it!(Array { elements })
+}
+
+// Counts the elements by the key `f` gives each, keeping the keys in the order first seen.
+fn count_by_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let array = match expect_array("count_by", &args[0]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    if let Err(e) = expect_function("count_by", &args[1]) {
+        return e;
+    }
+
+    let mut counts = IndexMap::new();
+    for element in &array.elements {
+        let key = apply_function(args[1].clone(), std::slice::from_ref(element));
+        if is_error(&key) {
+            return key;
+        }
+        let hash_key = match hash_key_of(&key) {
+            Ok(hash_key) => hash_key,
+            Err(e) => return e,
+        };
+        counts.entry(hash_key).or_insert((key, 0)).1 += 1;
+    }
+    box_it!(Hash {
+        pairs: counts
+            .into_iter()
+            .map(|(hash_key, (key, count))| {
+                let value = box_it!(Integer { value: count });
+                (hash_key, HashPair { key, value })
+            })
+            .collect(),
+    })
+}
+
+// `reduce([1, 2, 3], 0, f)` is `f(f(f(0, 1), 2), 3)`.
+fn reduce_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 3 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=3", args.len()),
+        );
+    }
+    let array = match expect_array("reduce", &args[0]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    if let Err(e) = expect_function("reduce", &args[2]) {
+        return e;
+    }
+
+    let mut acc = args[1].clone();
+    for element in &array.elements {
+        acc = apply_function(args[2].clone(), &[acc, element.clone()]);
+        if is_error(&acc) {
+            return acc;
+        }
+    }
+    acc
+}
+
+// Folds from the last element to the first, calling `f(element, acc)`, so
+// `fold_right([1, 2, 3], 0, f)` is `f(1, f(2, f(3, 0)))`.
+fn fold_right_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 3 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=3", args.len()),
+        );
+    }
+    let array = match expect_array("fold_right", &args[0]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    if let Err(e) = expect_function("fold_right", &args[2]) {
+        return e;
+    }
+
+    let mut acc = args[1].clone();
+    for element in array.elements.iter().rev() {
+        acc = apply_function(args[2].clone(), &[element.clone(), acc]);
+        if is_error(&acc) {
+            return acc;
+        }
+    }
+    acc
+}
+
+// Rotates left by `n`, so `rotate([1, 2, 3], 1)` is `[2, 3, 1]`; a negative `n` rotates right.
+fn rotate_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let array = match expect_array("rotate", &args[0]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    let n = match expect_integer("rotate", &args[1]) {
+        Ok(n) => n,
+        Err(e) => return e,
+    };
+
+    let mut elements = array.elements.clone();
+    if !elements.is_empty() {
+        let shift = n.rem_euclid(elements.len() as i64) as usize;
+        elements.rotate_left(shift);
+    }
+    box_it!(Array { elements })
+}
+
+// Takes any number of arrays and pairs up their elements by position, stopping at the end of
+// the shortest one: `zip([1, 2], ["a", "b"], [true])` is `[[1, "a", true]]`.
+fn zip_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.is_empty() {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got=0, want=at least 1"),
+        );
+    }
+    let mut arrays = Vec::new();
+    for arg in &args {
+        match expect_array("zip", arg) {
+            Ok(a) => arrays.push(a),
+            Err(e) => return e,
+        }
+    }
+
+    let len = arrays.iter().map(|a| a.elements.len()).min().unwrap();
+    let elements = (0..len)
+        .map(|i| -> ObjectRef {
+            box_it!(Array {
+                elements: arrays.iter().map(|a| a.elements[i].clone()).collect()
+            })
+        })
+        .collect();
+    box_it!(Array { elements })
+}
+
+// Inserts `args[1]` between every pair of adjacent elements, e.g. `intersperse([1, 2], 0)` is
+// `[1, 0, 2]`.
+fn intersperse_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    let array = match expect_array("intersperse", &args[0]) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+
+    let mut elements = Vec::new();
+    for (i, element) in array.elements.iter().enumerate() {
+        if i > 0 {
+            elements.push(args[1].clone());
+        }
+        elements.push(element.clone());
+    }
+    box_it!(Array { elements })
+}
+
+// Like JS `Function.prototype.bind`: the result calls `args[0]` with `args[1]` prepended to
+// whatever arguments it is given.
+fn bind_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.len() != 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=2", args.len()),
+        );
+    }
+    if let Err(e) = expect_function("bind", &args[0]) {
+        return e;
+    }
+    box_it!(BoundFunction {
+        function: args[0].clone(),
+        receiver: args[1].clone(),
+    })
+}
+
+fn to_json_string_builtin(args: Vec<ObjectRef>) -> ObjectRef {
+    if args.is_empty() || args.len() > 2 {
+        return new_error_of(
+            ErrorKind::Arity,
+            format_args!("wrong number of arguments. got={}, want=1 or 2", args.len()),
+        );
+    }
+    let pretty = match args.get(1) {
+        Some(arg) => match downcast_ref!(arg, Boolean) {
+            Some(b) => b.value,
+            None => {
+                return new_error_of(
+                    ErrorKind::Argument,
+                    format_args!(
+                        "argument to `to_json_string` must be BOOLEAN, got {}",
+                        arg.object_type().as_str()
+                    ),
+                )
+            }
+        },
+        None => false,
+    };
+
+    let json = if pretty {
+        to_json_pretty(&args[0])
+    } else {
+        to_json(&args[0])
+    };
+    match json {
+        Ok(value) => box_it!(StringObj { value }),
+        Err(message) => new_error_of(ErrorKind::Argument, format_args!("{}", message)),
+    }
+}
+
+// Adds a builtin that, like most, gets the first error among its arguments returned for it.
+fn register(
+    builtins: &mut HashMap<String, Builtin>,
+    name: &'static str,
+    func: fn(Vec<ObjectRef>) -> ObjectRef,
+) {
+    let builtin = Builtin {
+        name,
+        func,
+        takes_errors: false,
+    };
+    builtins.insert(name.to_string(), builtin);
+}
+
+lazy_static! {
+    pub static ref BUILTINS: HashMap<String, Builtin> = {
+        let mut builtins = HashMap::new();
+        register(&mut builtins, "len", len_builtin);
+        register(&mut builtins, "first", first_builtin);
+        register(&mut builtins, "last", last_builtin);
+        register(&mut builtins, "rest", rest_builtin);
+        register(&mut builtins, "take", take_builtin);
+        register(&mut builtins, "drop", drop_builtin);
+        register(&mut builtins, "push", push_builtin);
+        register(&mut builtins, "clone", clone_builtin);
+        #[cfg(feature = "io")]
+        register(&mut builtins, "puts", puts_builtin);
+        register(&mut builtins, "sort_by", sort_by_builtin);
+        register(&mut builtins, "to_upper", to_upper_builtin);
+        register(&mut builtins, "to_lower", to_lower_builtin);
+        register(&mut builtins, "trim", trim_builtin);
+        register(&mut builtins, "eq_ignore_case", eq_ignore_case_builtin);
+        register(
+            &mut builtins,
+            "contains_ignore_case",
+            contains_ignore_case_builtin,
+        );
+        register(&mut builtins, "all", all_builtin);
+        register(&mut builtins, "any", any_builtin);
+        register(&mut builtins, "table", table_builtin);
+        register(&mut builtins, "to_json_string", to_json_string_builtin);
+        register(&mut builtins, "bind", bind_builtin);
+        register(&mut builtins, "rotate", rotate_builtin);
+        register(&mut builtins, "type", type_builtin);
+        register(&mut builtins, "intersperse", intersperse_builtin);
+        register(&mut builtins, "str", str_builtin);
+        register(&mut builtins, "filter_hash", filter_hash_builtin);
+        register(&mut builtins, "zip", zip_builtin);
+        register(&mut builtins, "int", int_builtin);
+        register(&mut builtins, "benchmark", benchmark_builtin);
+        register(&mut builtins, "digits", digits_builtin);
+        register(&mut builtins, "digit_sum", digit_sum_builtin);
+        register(&mut builtins, "is_prime", is_prime_builtin);
+        register(&mut builtins, "factors", factors_builtin);
+        register(&mut builtins, "fold_right", fold_right_builtin);
+        register(&mut builtins, "map", map_builtin);
+        register(&mut builtins, "sort", sort_builtin);
+        register(
+            &mut builtins,
+            "group_consecutive",
+            group_consecutive_builtin,
+        );
+        register(&mut builtins, "transpose", transpose_builtin);
+        register(&mut builtins, "rle", rle_builtin);
+        register(&mut builtins, "rld", rld_builtin);
+        register(&mut builtins, "contains", contains_builtin);
+        register(&mut builtins, "reverse", reverse_builtin);
+        register(&mut builtins, "slice", slice_builtin);
+        register(&mut builtins, "binary_search", binary_search_builtin);
+        register(&mut builtins, "index", index_builtin);
+        register(&mut builtins, "keys", keys_builtin);
+        register(&mut builtins, "values", values_builtin);
+        register(&mut builtins, "delete", delete_builtin);
+        register(&mut builtins, "set", set_builtin);
+        register(&mut builtins, "add_multi", add_multi_builtin);
+        register(&mut builtins, "range", range_builtin);
+        register(&mut builtins, "split", split_builtin);
+        register(&mut builtins, "split_n", split_n_builtin);
+        register(&mut builtins, "join", join_builtin);
         builtins.insert(
-            "first".to_string(),
+            "error_kind".to_string(),
             Builtin {
-                func: first_builtin,
+                name: "error_kind",
+                func: error_kind_builtin,
+                takes_errors: true,
             },
         );
-        builtins.insert("last".to_string(), Builtin { func: last_builtin });
-        builtins.insert("rest".to_string(), Builtin { func: rest_builtin });
-        builtins.insert("push".to_string(), Builtin { func: push_builtin });
+        register(&mut builtins, "error", error_builtin);
+        register(&mut builtins, "format_number", format_number_builtin);
+        register(&mut builtins, "abs", abs_builtin);
+        register(&mut builtins, "pow", pow_builtin);
+        register(&mut builtins, "popcount", popcount_builtin);
+        register(&mut builtins, "bit", bit_builtin);
+        register(&mut builtins, "set_bit", set_bit_builtin);
+        register(&mut builtins, "min", min_builtin);
+        register(&mut builtins, "max", max_builtin);
+        register(&mut builtins, "seed", seed_builtin);
+        register(&mut builtins, "shuffle", shuffle_builtin);
+        register(&mut builtins, "sample", sample_builtin);
+        register(&mut builtins, "filter", filter_builtin);
+        register(&mut builtins, "count_by", count_by_builtin);
+        register(&mut builtins, "reduce", reduce_builtin);
+        register(&mut builtins, "char_at", char_at_builtin);
+        register(&mut builtins, "code_at", code_at_builtin);
         builtins
     };
 }
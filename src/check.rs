@@ -0,0 +1,442 @@
+use crate::ast::{Expr, Stmt};
+use crate::builtin::get_builtin;
+use crate::environment::PRELUDE;
+use crate::object::{Error, ErrorKind};
+use crate::parser::parse_program;
+use crate::visitor::{walk, walk_expr, walk_stmt, Visitor};
+use std::collections::{HashMap, HashSet};
+
+// Names bound directly in a scope, with how many times each is bound and the parameter count of
+// any function literal bound to them. Function literals, `with` and `let ... in` open scopes of
+// their own, so their bodies aren't looked into.
+#[derive(Default)]
+struct Declarations {
+    counts: HashMap<String, usize>,
+    arities: HashMap<String, usize>,
+}
+
+impl Declarations {
+    fn add(&mut self, name: &str) {
+        *self.counts.entry(name.to_string()).or_default() += 1;
+    }
+
+    fn rebound(&self) -> impl Iterator<Item = &String> {
+        self.counts
+            .iter()
+            .filter(|(_, count)| **count > 1)
+            .map(|(name, _)| name)
+    }
+
+    fn into_scope(self, rebound: &HashSet<String>) -> Scope {
+        let arities = self.arities;
+        self.counts
+            .into_keys()
+            .map(|name| {
+                let arity = arities.get(&name).filter(|_| !rebound.contains(&name));
+                let arity = arity.copied();
+                (name, arity)
+            })
+            .collect()
+    }
+}
+
+impl Visitor for Declarations {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let {
+                ref name,
+                ref value,
+                ..
+            }
+            | Stmt::Const {
+                ref name,
+                ref value,
+                ..
+            } => {
+                self.add(name);
+                if let Expr::FuncLit { ref parameters, .. } = **value {
+                    self.arities.insert(name.clone(), parameters.len());
+                }
+            }
+            Stmt::LetDestructure { ref names, .. } => {
+                for name in names.iter().filter(|name| *name != "_") {
+                    self.add(name);
+                }
+            }
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::FuncLit { .. } | Expr::With { .. } | Expr::LetIn { .. } => {}
+            _ => walk_expr(self, expr),
+        }
+    }
+}
+
+// Every name that is reassigned anywhere, so a function bound to it can't be relied on.
+#[derive(Default)]
+struct Assignments {
+    names: HashSet<String>,
+}
+
+impl Visitor for Assignments {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        if let Stmt::Assign { ref name, .. } = stmt {
+            self.names.insert(name.clone());
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+// Each binding maps to the parameter count of the function literal it's bound to, when that's
+// the only thing it's ever bound to.
+type Scope = HashMap<String, Option<usize>>;
+
+struct Checker {
+    scopes: Vec<Scope>,
+    // Every top-level binding, including ones made after the current statement.
+    globals: Scope,
+    prelude: HashSet<String>,
+    // Names that are reassigned, or bound more than once in some scope.
+    rebound: HashSet<String>,
+    function_depth: usize,
+    errors: Vec<Error>,
+}
+
+impl Checker {
+    fn error(&mut self, kind: ErrorKind, message: String, line: usize) {
+        self.errors.push(Error {
+            message,
+            kind,
+            line: Some(line),
+        });
+    }
+
+    // The innermost binding of `name`, if it is known to be bound at all.
+    fn lookup(&self, name: &str) -> Option<Option<usize>> {
+        if let Some(arity) = self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            return Some(*arity);
+        }
+        // A function body only runs once it's called, by which time any top-level binding may
+        // have been made.
+        if self.function_depth > 0 {
+            if let Some(arity) = self.globals.get(name) {
+                return Some(*arity);
+            }
+        }
+        (get_builtin(name).is_some() || self.prelude.contains(name)).then_some(None)
+    }
+
+    fn declare(&mut self, name: &str, value: Option<&Expr>) {
+        let arity = match value {
+            Some(Expr::FuncLit { parameters, .. }) if !self.rebound.contains(name) => {
+                Some(parameters.len())
+            }
+            _ => None,
+        };
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), arity);
+    }
+
+    fn reference(&mut self, name: &str, line: usize) {
+        if self.lookup(name).is_none() {
+            self.error(
+                ErrorKind::UnknownIdentifier,
+                format!("identifier not found: {}", name),
+                line,
+            );
+        }
+    }
+}
+
+impl Visitor for Checker {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let {
+                ref name,
+                ref value,
+                ..
+            }
+            | Stmt::Const {
+                ref name,
+                ref value,
+                ..
+            } => {
+                self.visit_expr(value);
+                self.declare(name, Some(value));
+            }
+            Stmt::LetDestructure {
+                ref names,
+                ref value,
+                ..
+            } => {
+                self.visit_expr(value);
+                for name in names.iter().filter(|name| *name != "_") {
+                    self.declare(name, None);
+                }
+            }
+            Stmt::Assign {
+                ref name,
+                ref value,
+                span,
+            } => {
+                self.visit_expr(value);
+                self.reference(name, span.line);
+            }
+            _ => walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Identifier(ref name, span) => self.reference(name, span.line),
+            Expr::Call {
+                ref function,
+                ref arguments,
+                span,
+            } => {
+                if let Expr::Identifier(ref name, _) = **function {
+                    if let Some(Some(want)) = self.lookup(name) {
+                        if want != arguments.len() {
+                            self.error(
+                                ErrorKind::ArgumentCount,
+                                format!(
+                                    "call to {} does not match its parameters. got={}, want={}",
+                                    name,
+                                    arguments.len(),
+                                    want
+                                ),
+                                span.line,
+                            );
+                        }
+                    }
+                }
+                walk_expr(self, expr);
+            }
+            // NOTE: Bindings in a function body are visible anywhere in it, so closures can
+            // refer to ones made after they were created.
+            Expr::FuncLit {
+                ref parameters,
+                ref body,
+            } => {
+                let mut declarations = Declarations::default();
+                declarations.visit_stmt(body);
+                self.rebound.extend(declarations.rebound().cloned());
+                let mut scope = declarations.into_scope(&self.rebound);
+                let mut seen = HashSet::new();
+                for param in parameters {
+                    if let Expr::Identifier(ref name, span) = **param {
+                        if name != "_" && !seen.insert(name) {
+                            self.error(
+                                ErrorKind::Other,
+                                format!("duplicate parameter: {}", name),
+                                span.line,
+                            );
+                        }
+                        scope.insert(name.clone(), None);
+                    }
+                }
+                self.scopes.push(scope);
+                self.function_depth += 1;
+                self.visit_stmt(body);
+                self.function_depth -= 1;
+                self.scopes.pop();
+            }
+            Expr::With {
+                ref bindings,
+                ref body,
+            } => {
+                self.scopes.push(Scope::new());
+                for (name, value) in bindings {
+                    self.visit_expr(value);
+                    self.declare(name, None);
+                }
+                self.visit_stmt(body);
+                self.scopes.pop();
+            }
+            Expr::LetIn {
+                ref name,
+                ref value,
+                ref body,
+            } => {
+                self.scopes.push(Scope::new());
+                self.visit_expr(value);
+                self.declare(name, None);
+                self.visit_expr(body);
+                self.scopes.pop();
+            }
+            _ => walk_expr(self, expr),
+        }
+    }
+}
+
+/// Parses `source` and checks it for mistakes that can be found without running it: top-level
+/// references to names that are never bound, duplicate parameters, and direct calls to a
+/// `let`-bound function literal with the wrong number of arguments. A parse error is returned
+/// on its own, as the only error.
+///
+/// The first two fail the same way when run. Argument counts aren't checked when calling, so
+/// those are reported with `ErrorKind::ArgumentCount` as likely mistakes rather than errors.
+///
+/// Names from the prelude count as bound, as they are in an `Interpreter`.
+pub fn validate(source: &str) -> Result<(), Vec<Error>> {
+    let program = parse_program(source).map_err(|message| {
+        vec![Error {
+            message,
+            kind: ErrorKind::Other,
+            line: None,
+        }]
+    })?;
+
+    let mut declarations = Declarations::default();
+    walk(&mut declarations, &program);
+    let mut assignments = Assignments::default();
+    walk(&mut assignments, &program);
+    // A name bound more than once may end up bound to something else by the time it's called.
+    let mut rebound = assignments.names;
+    rebound.extend(declarations.rebound().cloned());
+
+    let mut prelude_declarations = Declarations::default();
+    walk(&mut prelude_declarations, &PRELUDE);
+
+    let mut checker = Checker {
+        scopes: vec![Scope::new()],
+        globals: declarations.into_scope(&rebound),
+        prelude: prelude_declarations.counts.into_keys().collect(),
+        rebound,
+        function_depth: 0,
+        errors: Vec::new(),
+    };
+    walk(&mut checker, &program);
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(checker.errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Object;
+
+    fn errors(input: &str) -> Vec<(ErrorKind, String)> {
+        match validate(input) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors
+                .into_iter()
+                .map(|error| (error.kind, error.inspect()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_valid_programs() {
+        let inputs = vec![
+            "let a = 1; first([len([a])]);",
+            "let f = fn(n) { if (n > 0) { f(n - 1); } else { g(); }; }; let g = fn() { 0; }; f(3);",
+            "let f = fn() { let h = fn() { x; }; let x = 1; h(); }; f();",
+            "if (true) { let x = 1; }; x;",
+            "with (a = 1, b = a) { let c = b; c; };",
+            "let x = 1 in x + 1;",
+            "let [a, _, b] = [1, 2, 3]; a + b;",
+            "sum([1, 2]); identity(1);",
+            "let i = 0; i = i + 1;",
+            "let f = fn(x) { x; }; f = fn(x, y) { x; }; f(1, 2);",
+            "let f = fn(x) { x; }; let f = fn(x, y) { x; }; f(1, 2);",
+            "let f = fn(x) { x; }; let g = fn(f) { f(1, 2); }; g(len);",
+        ];
+
+        for input in inputs {
+            assert_eq!(errors(input), vec![], "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_undefined_identifiers() {
+        let tests = vec![
+            ("foobar;", vec!["identifier not found: foobar (line 1)"]),
+            (
+                "let a = b;\nlet c = a + d;",
+                vec![
+                    "identifier not found: b (line 1)",
+                    "identifier not found: d (line 2)",
+                ],
+            ),
+            ("x; let x = 1;", vec!["identifier not found: x (line 1)"]),
+            ("y = 1;", vec!["identifier not found: y (line 1)"]),
+            (
+                "let f = fn() { z; }; f();",
+                vec!["identifier not found: z (line 1)"],
+            ),
+            (
+                "let x = 1 in x; x;",
+                vec!["identifier not found: x (line 1)"],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let found = errors(input);
+            assert!(found
+                .iter()
+                .all(|(kind, _)| *kind == ErrorKind::UnknownIdentifier));
+            let messages: Vec<_> = found.into_iter().map(|(_, message)| message).collect();
+            assert_eq!(messages, expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_duplicate_parameters() {
+        assert_eq!(
+            errors("let f = fn(a, b,\n a) { a; };"),
+            vec![(
+                ErrorKind::Other,
+                "duplicate parameter: a (line 2)".to_string()
+            )]
+        );
+        assert_eq!(errors("let f = fn(_, x, _) { x; };"), vec![]);
+    }
+
+    #[test]
+    fn test_arity_mismatches() {
+        let tests = vec![
+            (
+                "let add = fn(a, b) { a + b; };\nadd(1);",
+                "call to add does not match its parameters. got=1, want=2 (line 2)",
+            ),
+            (
+                "let f = fn() { g(1, 2); }; const g = fn(x) { x; };",
+                "call to g does not match its parameters. got=2, want=1 (line 1)",
+            ),
+            (
+                "let f = fn(x) { x; }; [f(1), f()];",
+                "call to f does not match its parameters. got=0, want=1 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                errors(input),
+                vec![(ErrorKind::ArgumentCount, expected.to_string())],
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let found = errors("let = 1;");
+        assert_eq!(found.len(), 1);
+        assert!(
+            found[0].1.starts_with("parse error at line 1"),
+            "{:?}",
+            found
+        );
+    }
+}
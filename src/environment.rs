@@ -1,40 +1,295 @@
-use crate::{
-    box_it,
-    object::{Null, ObjectRef},
-};
-use std::collections::HashMap;
+use crate::ast::{Expr, Program};
+use crate::evaluator::{eval_program, is_error, EvalConfig, EvalObserver};
+use crate::object::ObjectRef;
+use crate::parser::parse_program;
+use lazy_static::lazy_static;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::rc::Rc;
 
+// A cheap handle to a scope. Clones share the same bindings, so a closure holding a clone of
+// the environment it was created in sees (and makes) later changes to it.
+//
+// NOTE: A function stored in the scope it captures forms an `Rc` cycle and is never freed.
 #[derive(Clone)]
 pub struct Environment {
+    scope: Rc<RefCell<Scope>>,
+    config: EvalConfig,
+    // Shared by every environment enclosed in this one, so setting an observer also reaches
+    // the scopes of closures that were created before it.
+    observer: Rc<RefCell<Option<Rc<dyn EvalObserver>>>>,
+}
+
+struct Scope {
     store: HashMap<String, ObjectRef>,
-    outer: Option<Box<Environment>>,
+    constants: HashSet<String>,
+    outer: Option<Environment>,
+}
+
+lazy_static! {
+    // Helpers written in Monkey itself, see `Environment::load_prelude`. They're parsed once,
+    // however many environments load them.
+    pub(crate) static ref PRELUDE: Program =
+        parse_program(include_str!("prelude.mk")).expect("prelude should parse");
+}
+
+// Every function call opens a scope, so the stores of dropped scopes are kept (cleared) for
+// reuse instead of allocating a new map per call.
+const STORE_POOL_CAPACITY: usize = 64;
+
+thread_local! {
+    static STORE_POOL: RefCell<Vec<HashMap<String, ObjectRef>>> = const { RefCell::new(Vec::new()) };
+}
+
+fn pooled_store() -> HashMap<String, ObjectRef> {
+    STORE_POOL
+        .try_with(|pool| pool.borrow_mut().pop())
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let mut store = mem::take(&mut self.store);
+        if store.capacity() == 0 {
+            return;
+        }
+        // NOTE: Clearing may drop closures and with them other scopes, so the pool must not be
+        // borrowed until it's done.
+        store.clear();
+        let _ = STORE_POOL.try_with(|pool| {
+            let mut pool = pool.borrow_mut();
+            if pool.len() < STORE_POOL_CAPACITY {
+                pool.push(store);
+            }
+        });
+    }
 }
 
 impl Environment {
     pub fn new() -> Self {
-        Environment {
-            store: HashMap::new(),
-            outer: None,
-        }
+        Environment::with_config(EvalConfig::default())
+    }
+
+    pub fn with_config(config: EvalConfig) -> Self {
+        Environment::with_outer(None, config)
+    }
+
+    pub fn new_with_prelude() -> Self {
+        let mut env = Environment::new();
+        env.load_prelude();
+        env
+    }
+
+    // Defines the helpers from `prelude.mk` in this scope. They're ordinary bindings, so user
+    // code can shadow them.
+    pub fn load_prelude(&mut self) {
+        let result = eval_program(&PRELUDE, self).expect("prelude should evaluate");
+        debug_assert!(!is_error(&result), "{}", result.inspect());
     }
 
     pub fn new_enclosed(outer: &Environment) -> Self {
+        Environment::with_outer(Some(outer.clone()), outer.config)
+    }
+
+    fn with_outer(outer: Option<Environment>, config: EvalConfig) -> Self {
+        let observer = outer
+            .as_ref()
+            .map(|outer| outer.observer.clone())
+            .unwrap_or_default();
         Environment {
-            store: HashMap::new(),
-            outer: Some(box_it!(outer.clone())),
+            scope: Rc::new(RefCell::new(Scope {
+                store: pooled_store(),
+                constants: HashSet::new(),
+                outer,
+            })),
+            config,
+            observer,
         }
     }
 
+    pub fn set_observer(&mut self, observer: Option<Rc<dyn EvalObserver>>) {
+        *self.observer.borrow_mut() = observer;
+    }
+
+    #[inline]
+    pub fn notify(&self, expr: &Expr, value: &ObjectRef) {
+        if let Some(observer) = self.observer.borrow().as_ref() {
+            observer.on_expr(expr, value);
+        }
+    }
+
+    pub fn is_observed(&self) -> bool {
+        self.observer.borrow().is_some()
+    }
+
+    pub fn config(&self) -> EvalConfig {
+        self.config
+    }
+
     pub fn get(&self, name: &str) -> Option<ObjectRef> {
-        self.store
+        let scope = self.scope.borrow();
+        scope
+            .store
             .get(name)
             .cloned()
-            .or_else(|| self.outer.as_ref().and_then(|outer| outer.get(name)))
+            .or_else(|| scope.outer.as_ref().and_then(|outer| outer.get(name)))
     }
 
     pub fn set(&mut self, name: String, value: ObjectRef) -> ObjectRef {
-        self.store
-            .insert(name, value)
-            .unwrap_or_else(|| box_it!(Null))
+        self.scope.borrow_mut().store.insert(name, value.clone());
+        value
+    }
+
+    pub fn set_const(&mut self, name: String, value: ObjectRef) -> ObjectRef {
+        self.scope.borrow_mut().constants.insert(name.clone());
+        self.set(name, value)
+    }
+
+    // NOTE: Only the current scope is checked, so constants can still be shadowed by bindings
+    // in an enclosed scope.
+    pub fn is_const(&self, name: &str) -> bool {
+        self.scope.borrow().constants.contains(name)
+    }
+
+    // Copies the bindings of `other`'s own scope (not its outer ones) into this scope, replacing
+    // any with the same name. Each copied binding stays a constant only if it was one in `other`.
+    pub fn merge(&mut self, other: &Environment) {
+        if Rc::ptr_eq(&self.scope, &other.scope) {
+            return;
+        }
+        let other = other.scope.borrow();
+        let mut scope = self.scope.borrow_mut();
+        for (name, value) in &other.store {
+            scope.store.insert(name.clone(), value.clone());
+            if other.constants.contains(name) {
+                scope.constants.insert(name.clone());
+            } else {
+                scope.constants.remove(name);
+            }
+        }
+    }
+
+    // Rebinds an existing name in the nearest scope that defines it.
+    pub fn assign(&mut self, name: &str, value: ObjectRef) -> Result<ObjectRef, String> {
+        if self.scope.borrow().store.contains_key(name) {
+            if self.is_const(name) {
+                return Err(format!("cannot assign to constant {}", name));
+            }
+            return Ok(self.set(name.to_string(), value));
+        }
+        let outer = self.scope.borrow().outer.clone();
+        match outer {
+            Some(mut outer) => outer.assign(name, value),
+            None => Err(format!("identifier not found: {}", name)),
+        }
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::box_it;
+    use crate::object::Integer;
+
+    #[test]
+    fn test_set_returns_stored_value() {
+        let mut env = Environment::new();
+
+        let first = env.set("a".to_string(), box_it!(Integer { value: 1 }));
+        assert_eq!(first.inspect(), "1");
+
+        let rebound = env.set("a".to_string(), box_it!(Integer { value: 2 }));
+        assert_eq!(rebound.inspect(), "2");
+        assert_eq!(env.get("a").unwrap().inspect(), "2");
+    }
+
+    #[test]
+    fn test_pooled_store_starts_empty() {
+        let outer = Environment::new();
+        for i in 0..3 {
+            let mut inner = Environment::new_enclosed(&outer);
+            assert!(inner.get("a").is_none());
+            inner.set("a".to_string(), box_it!(Integer { value: i }));
+            assert_eq!(inner.get("a").unwrap().inspect(), i.to_string());
+        }
+    }
+
+    #[test]
+    fn test_assign() {
+        let mut outer = Environment::new();
+        outer.set("a".to_string(), box_it!(Integer { value: 1 }));
+        outer.set_const("PI".to_string(), box_it!(Integer { value: 3 }));
+
+        let mut inner = Environment::new_enclosed(&outer);
+        let assigned = inner.assign("a", box_it!(Integer { value: 2 }));
+        assert_eq!(assigned.unwrap().inspect(), "2");
+        assert_eq!(inner.get("a").unwrap().inspect(), "2");
+        assert_eq!(outer.get("a").unwrap().inspect(), "2");
+
+        let result = inner.assign("PI", box_it!(Integer { value: 4 }));
+        assert_eq!(result.err().unwrap(), "cannot assign to constant PI");
+        let result = inner.assign("b", box_it!(Integer { value: 4 }));
+        assert_eq!(result.err().unwrap(), "identifier not found: b");
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut base = Environment::new();
+        base.set("a".to_string(), box_it!(Integer { value: 1 }));
+        base.set_const("b".to_string(), box_it!(Integer { value: 2 }));
+
+        let mut outer = Environment::new();
+        let mut extra = Environment::new_enclosed(&outer);
+        outer.set("hidden".to_string(), box_it!(Integer { value: 0 }));
+        extra.set("b".to_string(), box_it!(Integer { value: 20 }));
+        extra.set_const("c".to_string(), box_it!(Integer { value: 30 }));
+
+        base.merge(&extra);
+        assert_eq!(base.get("a").unwrap().inspect(), "1");
+        assert_eq!(base.get("b").unwrap().inspect(), "20");
+        assert_eq!(base.get("c").unwrap().inspect(), "30");
+        assert!(base.get("hidden").is_none());
+        assert!(!base.is_const("b"));
+        assert!(base.is_const("c"));
+        // `other` is left as it was.
+        assert!(extra.get("a").is_none());
+
+        let same = base.clone();
+        base.merge(&same);
+        assert_eq!(base.get("c").unwrap().inspect(), "30");
+    }
+
+    #[test]
+    fn test_prelude() {
+        let mut env = Environment::new_with_prelude();
+        let tests = vec![
+            ("identity(5);", "5"),
+            ("compose(fn(x) { x + 1; }, fn(x) { x * 2; })(5);", "11"),
+            ("sum([1, 2, 3]);", "6"),
+            ("product([1, 2, 3, 4]);", "24"),
+            (
+                "flat_map([1, 2], fn(x) { [x, x * 10]; });",
+                "[1, 10, 2, 20]",
+            ),
+            ("find([1, 2, 3, 4], fn(x) { x > 2; });", "3"),
+            ("find([1, 2], fn(x) { x > 2; });", "null"),
+            ("let sum = fn(a) { 0; }; sum([1, 2]);", "0"),
+        ];
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let result = eval_program(&program, &mut env).unwrap();
+            assert_eq!(result.inspect(), expected, "{}", input);
+        }
+
+        assert!(Environment::new().get("sum").is_none());
     }
 }
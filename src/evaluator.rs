@@ -1,18 +1,70 @@
-use crate::ast::{Expr, Node, Opcode, Program, Stmt};
+use crate::ast::{Expr, Node, Opcode, Program, Span, Stmt};
 use crate::builtin::get_builtin;
 use crate::environment::Environment;
 use crate::object::{
-    Array, Boolean, Builtin, Error, Function, Hash, HashPair, Hashable, Integer, Null, ObjectRef,
-    ReturnValue, StringObj,
+    Array, Boolean, BoundFunction, Builtin, Error, ErrorKind, Function, Hash, HashKey, HashPair,
+    HashPairs, Hashable, Integer, Null, ObjectRef, ObjectType, ReturnValue, StringObj,
 };
 use crate::{box_it, downcast_ref};
+use std::cell::Cell;
+use std::collections::HashSet;
 use std::fmt;
 
+thread_local! {
+    // Every boolean and null the evaluator and builtins produce is a handle to one of these, so
+    // making one doesn't allocate.
+    static TRUE: ObjectRef = box_it!(Boolean { value: true });
+    static FALSE: ObjectRef = box_it!(Boolean { value: false });
+    static NULL: ObjectRef = box_it!(Null);
+}
+
+// What indexing an array past either end evaluates to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndexOutOfRange {
+    #[default]
+    Null,
+    Error,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EvalConfig {
+    pub index_out_of_range: IndexOutOfRange,
+    // How many Monkey function calls may be nested before a call fails instead of overflowing
+    // the Rust stack.
+    pub max_call_depth: usize,
+}
+
+impl Default for EvalConfig {
+    fn default() -> Self {
+        EvalConfig {
+            index_out_of_range: IndexOutOfRange::default(),
+            max_call_depth: 1000,
+        }
+    }
+}
+
+// Each Monkey call makes sure at least `STACK_RED_ZONE` bytes of Rust stack are left, moving onto
+// a new heap-allocated segment of `STACK_SEGMENT` bytes if not. That way `max_call_depth` is what
+// limits recursion on any thread, however small its stack.
+const STACK_RED_ZONE: usize = 256 * 1024;
+const STACK_SEGMENT: usize = 4 * 1024 * 1024;
+
+thread_local! {
+    // The number of Monkey function calls currently being evaluated.
+    static CALL_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Told about every expression once it has been evaluated, so children are reported before their
+/// parents. See `Environment::set_observer`.
+pub trait EvalObserver {
+    fn on_expr(&self, expr: &Expr, value: &ObjectRef);
+}
+
 pub fn eval_program(program: &Program, env: &mut Environment) -> Result<ObjectRef, String> {
     Ok(program.eval(env))
 }
 
-fn is_error(object: &ObjectRef) -> bool {
+pub fn is_error(object: &ObjectRef) -> bool {
     downcast_ref!(object, Error).is_some()
 }
 
@@ -22,14 +74,14 @@ fn eval(node: &dyn Node, env: &mut Environment) -> ObjectRef {
 
 impl Node for Program {
     fn eval(&self, env: &mut Environment) -> ObjectRef {
-        let mut result: ObjectRef = box_it!(Null);
+        let mut result: ObjectRef = null();
         for stmt in &self.statements {
             result = eval(stmt.as_ref(), env);
             if let Some(return_value) = downcast_ref!(result, ReturnValue) {
                 return return_value.value.clone();
             }
 
-            if let Some(_) = downcast_ref!(result, Error) {
+            if downcast_ref!(result, Error).is_some() {
                 return result;
             }
         }
@@ -39,158 +91,328 @@ impl Node for Program {
 
 impl Node for Stmt {
     fn eval(&self, env: &mut Environment) -> ObjectRef {
-        match self {
-            Stmt::Let {
-                ref name,
-                ref value,
-            } => {
-                let value = eval(value.as_ref(), env);
-                if is_error(&value) {
-                    return value;
+        locate(eval_statement(self, env), self.span())
+    }
+}
+
+fn eval_statement(stmt: &Stmt, env: &mut Environment) -> ObjectRef {
+    match stmt {
+        Stmt::Let {
+            ref name,
+            ref value,
+            ..
+        } => {
+            let value = eval(value.as_ref(), env);
+            if is_error(&value) {
+                return value;
+            }
+            if env.is_const(name) {
+                return new_error_of(
+                    ErrorKind::Constant,
+                    format_args!("cannot assign to constant {}", name),
+                );
+            }
+            env.set(name.clone(), value)
+        }
+        // `_` matches an element without binding it.
+        Stmt::LetDestructure {
+            ref names,
+            ref value,
+            ..
+        } => {
+            let value = eval(value.as_ref(), env);
+            if is_error(&value) {
+                return value;
+            }
+            let array = match downcast_ref!(value, Array) {
+                Some(array) => array,
+                None => {
+                    return new_error_of(
+                        ErrorKind::TypeMismatch,
+                        format_args!(
+                            "cannot destructure {} as ARRAY",
+                            value.object_type().as_str()
+                        ),
+                    )
                 }
-                env.set(name.clone(), value)
+            };
+            if array.elements.len() != names.len() {
+                return new_error(format_args!(
+                    "cannot destructure array of length {} into {} names",
+                    array.elements.len(),
+                    names.len()
+                ));
             }
-            Stmt::Return { ref return_value } => {
-                let value = eval(return_value.as_ref(), env);
-                if is_error(&value) {
-                    return value;
+            if let Some(name) = names.iter().find(|name| env.is_const(name)) {
+                return new_error_of(
+                    ErrorKind::Constant,
+                    format_args!("cannot assign to constant {}", name),
+                );
+            }
+            for (name, element) in names.iter().zip(array.elements.iter()) {
+                if name != "_" {
+                    env.set(name.clone(), element.clone());
                 }
-                box_it!(ReturnValue { value })
             }
-            Stmt::Expr { ref expression } => eval(expression.as_ref(), env),
-            Stmt::Block { ref statements } => {
-                let mut result: ObjectRef = box_it!(Null);
-                for stmt in statements {
-                    result = eval(stmt.as_ref(), env);
-                    if let Some(_) = downcast_ref!(result, ReturnValue) {
-                        return result;
-                    } else if let Some(_) = downcast_ref!(result, Error) {
-                        return result;
-                    }
+            value
+        }
+        Stmt::Const {
+            ref name,
+            ref value,
+            ..
+        } => {
+            let value = eval(value.as_ref(), env);
+            if is_error(&value) {
+                return value;
+            }
+            if env.is_const(name) {
+                return new_error_of(
+                    ErrorKind::Constant,
+                    format_args!("cannot assign to constant {}", name),
+                );
+            }
+            env.set_const(name.clone(), value)
+        }
+        Stmt::Assign {
+            ref name,
+            ref value,
+            ..
+        } => {
+            let value = eval(value.as_ref(), env);
+            if is_error(&value) {
+                return value;
+            }
+            match env.assign(name, value) {
+                Ok(value) => value,
+                Err(message) => {
+                    let kind = if env.get(name).is_some() {
+                        ErrorKind::Constant
+                    } else {
+                        ErrorKind::UnknownIdentifier
+                    };
+                    new_error_of(kind, format_args!("{}", message))
+                }
+            }
+        }
+        Stmt::Return { ref return_value } => {
+            let value = eval(return_value.as_ref(), env);
+            if is_error(&value) {
+                return value;
+            }
+            box_it!(ReturnValue { value })
+        }
+        Stmt::Expr { ref expression } => eval(expression.as_ref(), env),
+        Stmt::Block { ref statements } => {
+            let mut result: ObjectRef = null();
+            for stmt in statements {
+                result = eval(stmt.as_ref(), env);
+                if downcast_ref!(result, ReturnValue).is_some() || is_error(&result) {
+                    return result;
                 }
-                result
             }
+            result
+        }
+        Stmt::While {
+            ref condition,
+            ref body,
+        } => {
+            loop {
+                let condition_value = eval(condition.as_ref(), env);
+                if is_error(&condition_value) {
+                    return condition_value;
+                }
+                if !is_truthy(&condition_value) {
+                    break;
+                }
+                let result = eval(body.as_ref(), env);
+                if downcast_ref!(result, ReturnValue).is_some() || is_error(&result) {
+                    return result;
+                }
+            }
+            null()
         }
     }
 }
 
 impl Node for Expr {
     fn eval(&self, env: &mut Environment) -> ObjectRef {
-        match self {
-            Expr::Number(n) => box_it!(Integer { value: *n }),
-            Expr::Identifier(ident) => {
-                let value = eval_identifier_expression(ident, env);
+        let value = locate(eval_expression(self, env), self.span());
+        env.notify(self, &value);
+        value
+    }
+}
+
+fn eval_expression(expr: &Expr, env: &mut Environment) -> ObjectRef {
+    match expr {
+        Expr::Number(n) => box_it!(Integer { value: *n }),
+        Expr::Identifier(ident, _) => {
+            let value = eval_identifier_expression(ident, env);
+            if is_error(&value) {
+                return value;
+            }
+            value
+        }
+        Expr::Boolean(b) => eval_native_boolean(b),
+        Expr::StringLit(s) => box_it!(StringObj { value: s.clone() }),
+        Expr::InfixOp {
+            ref left,
+            ref operator,
+            ref right,
+            ..
+        } => {
+            let left_value = eval(left.as_ref(), env);
+            if is_error(&left_value) {
+                return left_value;
+            }
+            let right_value = eval(right.as_ref(), env);
+            if is_error(&right_value) {
+                return right_value;
+            }
+            eval_infix_expression(operator, &left_value, &right_value)
+        }
+        Expr::PrefixOp {
+            ref operator,
+            ref right,
+            ..
+        } => {
+            let right_value = eval(right.as_ref(), env);
+            if is_error(&right_value) {
+                return right_value;
+            }
+            eval_prefix_expression(operator, &right_value)
+        }
+        // An `if` without an `else` whose condition is false evaluates to null, so it can be used
+        // wherever a value is expected.
+        Expr::If {
+            ref condition,
+            ref consequence,
+            ref alternative,
+        } => {
+            let condition_value = eval(condition.as_ref(), env);
+            if is_error(&condition_value) {
+                return condition_value;
+            }
+            if is_truthy(&condition_value) {
+                eval(consequence.as_ref(), env)
+            } else {
+                match alternative {
+                    Some(alt) => eval(alt.as_ref(), env),
+                    None => null(),
+                }
+            }
+        }
+        // Each binding is evaluated in the new scope, so it can refer to the ones before it.
+        Expr::With {
+            ref bindings,
+            ref body,
+        } => {
+            let mut enclosed_env = Environment::new_enclosed(env);
+            for (name, value) in bindings {
+                let value = eval(value.as_ref(), &mut enclosed_env);
                 if is_error(&value) {
                     return value;
                 }
-                value
+                enclosed_env.set(name.clone(), value);
             }
-            Expr::Boolean(b) => eval_native_boolean(b),
-            Expr::StringLit(s) => box_it!(StringObj {
-                // NOTE: Trim the double quotes from the Expr::StringLit. This is because it
-                // contains the double quotes in the AST node value.
-                // TODO: This should be done in the parser.
-                value: s.trim_matches('"').to_string()
-            }),
-            Expr::InfixOp {
-                ref left,
-                ref operator,
-                ref right,
-            } => {
-                let left_value = eval(left.as_ref(), env);
-                if is_error(&left_value) {
-                    return left_value;
-                }
-                let right_value = eval(right.as_ref(), env);
-                if is_error(&right_value) {
-                    return right_value;
-                }
-                eval_infix_expression(operator, &left_value, &right_value)
+            eval(body.as_ref(), &mut enclosed_env)
+        }
+        Expr::LetIn {
+            ref name,
+            ref value,
+            ref body,
+        } => {
+            let mut enclosed_env = Environment::new_enclosed(env);
+            let value = eval(value.as_ref(), &mut enclosed_env);
+            if is_error(&value) {
+                return value;
             }
-            Expr::PrefixOp {
-                ref operator,
-                ref right,
-            } => {
-                let right_value = eval(right.as_ref(), env);
-                if is_error(&right_value) {
-                    return right_value;
-                }
-                eval_prefix_expression(operator, &right_value)
+            enclosed_env.set(name.clone(), value);
+            eval(body.as_ref(), &mut enclosed_env)
+        }
+        // `cleanup` runs whether or not `body` failed. Its own result is dropped unless it is an
+        // error or a `return`, which take over from the body's like they would in any block.
+        Expr::Try {
+            ref body,
+            ref cleanup,
+        } => {
+            let result = eval(body.as_ref(), env);
+            let cleanup_result = eval(cleanup.as_ref(), env);
+            if is_error(&cleanup_result) || downcast_ref!(cleanup_result, ReturnValue).is_some() {
+                return cleanup_result;
             }
-            Expr::If {
-                ref condition,
-                ref consequence,
-                ref alternative,
-            } => {
-                let condition_value = eval(condition.as_ref(), env);
-                if is_truthy(&condition_value) {
-                    eval(consequence.as_ref(), env)
-                } else {
-                    match alternative {
-                        Some(alt) => eval(alt.as_ref(), env),
-                        None => box_it!(Null),
+            result
+        }
+        Expr::FuncLit {
+            ref parameters,
+            ref body,
+        } => {
+            // `_` marks a parameter that isn't used, so there can be several.
+            let mut seen = HashSet::new();
+            for param in parameters {
+                if let Expr::Identifier(name, _) = param.as_ref() {
+                    if name != "_" && !seen.insert(name) {
+                        return new_error(format_args!("duplicate parameter: {}", name));
                     }
                 }
             }
-            Expr::FuncLit {
-                ref parameters,
-                ref body,
-            } => {
-                box_it!(Function {
-                    parameters: parameters.clone(),
-                    body: body.clone(),
-                    env: env.clone(),
-                })
+            box_it!(Function {
+                parameters: parameters.clone(),
+                body: body.clone(),
+                env: env.clone(),
+            })
+        }
+        Expr::Call {
+            ref function,
+            ref arguments,
+            ..
+        } => {
+            let function = eval(function.as_ref(), env);
+            if is_error(&function) {
+                return function;
             }
-            Expr::Call {
-                ref function,
-                ref arguments,
-            } => {
-                let function = eval(function.as_ref(), env);
-                if is_error(&function) {
-                    return function;
-                }
-                let args = eval_expressions(arguments, env);
-                if args.len() == 1 && is_error(&args[0]) {
-                    return args[0].clone();
-                }
-                apply_function(function, args.as_slice())
+            match eval_arguments(&function, arguments, env) {
+                Ok(args) => apply_function(function, args.as_slice()),
+                Err(error) => error,
             }
-            Expr::ArrayLit { ref elements } => {
-                let elements = eval_expressions(elements, env);
-                if elements.len() == 1 && is_error(&elements[0]) {
-                    return elements[0].clone();
-                }
-                box_it!(Array { elements })
+        }
+        Expr::ArrayLit { ref elements } => {
+            let elements = eval_expressions(elements, env);
+            if elements.len() == 1 && is_error(&elements[0]) {
+                return elements[0].clone();
+            }
+            box_it!(Array { elements })
+        }
+        Expr::Index {
+            ref left,
+            ref index,
+            ..
+        } => {
+            let left = eval(left.as_ref(), env);
+            if is_error(&left) {
+                return left;
             }
-            Expr::Index {
-                ref left,
-                ref index,
-            } => {
-                let left = eval(left.as_ref(), env);
-                if is_error(&left) {
-                    return left;
-                }
 
-                let index = eval(index.as_ref(), env);
-                if is_error(&index) {
-                    return index;
-                }
-                eval_index_expression(&left, &index)
+            let index = eval(index.as_ref(), env);
+            if is_error(&index) {
+                return index;
             }
-            Expr::HashLit { ref pairs } => eval_hash_literal(pairs, env),
+            eval_index_expression(&left, &index, env.config())
         }
+        Expr::HashLit { ref pairs, .. } => eval_hash_literal(pairs, env),
     }
 }
 
 fn eval_infix_expression(operator: &Opcode, left: &ObjectRef, right: &ObjectRef) -> ObjectRef {
     if left.object_type() != right.object_type() {
-        return new_error(format_args!(
-            "type mismatch: {} {} {}",
-            left.object_type().as_str(),
-            operator.as_str(),
-            right.object_type().as_str()
-        ));
+        return new_error_of(
+            ErrorKind::TypeMismatch,
+            format_args!(
+                "type mismatch: {} {} {}",
+                left.object_type().as_str(),
+                operator.as_str(),
+                right.object_type().as_str()
+            ),
+        );
     }
     if let (Some(left_int), Some(right_int)) =
         (downcast_ref!(left, Integer), downcast_ref!(right, Integer))
@@ -201,41 +423,124 @@ fn eval_infix_expression(operator: &Opcode, left: &ObjectRef, right: &ObjectRef)
         downcast_ref!(right, StringObj),
     ) {
         eval_string_infix_expression(operator, left_str, right_str)
+    } else if matches!(
+        left.object_type(),
+        ObjectType::Array | ObjectType::Hash | ObjectType::Null
+    ) && matches!(operator, Opcode::Eq | Opcode::NotEq)
+    {
+        let equal = objects_equal(left, right);
+        eval_native_boolean(&(matches!(operator, Opcode::Eq) == equal))
     } else {
         match operator {
             Opcode::Eq | Opcode::NotEq => eval_boolean_infix_expression(operator, left, right),
-            _ => new_error(format_args!(
-                "unknown operator: {} {} {}",
-                left.object_type().as_str(),
-                operator.as_str(),
-                right.object_type().as_str()
-            )),
+            _ => new_error_of(
+                ErrorKind::UnknownOperator,
+                format_args!(
+                    "unknown operator: {} {} {}",
+                    left.object_type().as_str(),
+                    operator.as_str(),
+                    right.object_type().as_str()
+                ),
+            ),
         }
     }
 }
 
+// Arrays are equal when their elements are pairwise equal, and hashes when they hold the same
+// keys mapped to equal values, regardless of the order the pairs were inserted in. Values of
+// different types are never equal.
+pub fn objects_equal(left: &ObjectRef, right: &ObjectRef) -> bool {
+    if left.object_type() != right.object_type() {
+        return false;
+    }
+    if let (Some(l), Some(r)) = (downcast_ref!(left, Integer), downcast_ref!(right, Integer)) {
+        l.value == r.value
+    } else if let (Some(l), Some(r)) = (downcast_ref!(left, Boolean), downcast_ref!(right, Boolean))
+    {
+        l.value == r.value
+    } else if let (Some(l), Some(r)) = (
+        downcast_ref!(left, StringObj),
+        downcast_ref!(right, StringObj),
+    ) {
+        l.value == r.value
+    } else if downcast_ref!(left, Null).is_some() {
+        true
+    } else if let (Some(l), Some(r)) = (downcast_ref!(left, Array), downcast_ref!(right, Array)) {
+        l.elements.len() == r.elements.len()
+            && l.elements
+                .iter()
+                .zip(&r.elements)
+                .all(|(a, b)| objects_equal(a, b))
+    } else if let (Some(l), Some(r)) = (downcast_ref!(left, Hash), downcast_ref!(right, Hash)) {
+        l.pairs.len() == r.pairs.len()
+            && l.pairs.iter().all(|(key, pair)| {
+                r.pairs.get(key).is_some_and(|other| {
+                    objects_equal(&pair.key, &other.key) && objects_equal(&pair.value, &other.value)
+                })
+            })
+    } else {
+        false
+    }
+}
+
 fn eval_integer_infix_expression(operator: &Opcode, left: &Integer, right: &Integer) -> ObjectRef {
     match operator {
-        Opcode::Add => box_it!(Integer {
-            value: left.value + right.value,
-        }),
-        Opcode::Sub => box_it!(Integer {
-            value: left.value - right.value,
-        }),
-        Opcode::Mul => box_it!(Integer {
-            value: left.value * right.value,
-        }),
-        Opcode::Div => box_it!(Integer {
-            value: left.value / right.value,
-        }),
+        // NOTE: Results that don't fit in an i64 are an error rather than wrapping around.
+        Opcode::Add | Opcode::Sub | Opcode::Mul => {
+            let value = match operator {
+                Opcode::Add => left.value.checked_add(right.value),
+                Opcode::Sub => left.value.checked_sub(right.value),
+                _ => left.value.checked_mul(right.value),
+            };
+            match value {
+                Some(value) => box_it!(Integer { value }),
+                None => new_error(format_args!(
+                    "integer overflow: {} {} {}",
+                    left.value,
+                    operator.as_str(),
+                    right.value
+                )),
+            }
+        }
+        // NOTE: Division by zero (and `i64::MIN / -1`) is an error rather than a value, as there is
+        // no NaN or infinity to produce.
+        Opcode::Div => match left.value.checked_div(right.value) {
+            Some(value) => box_it!(Integer { value }),
+            None if right.value == 0 => {
+                new_error_of(ErrorKind::DivideByZero, format_args!("division by zero"))
+            }
+            None => new_error(format_args!(
+                "integer overflow: {} / {}",
+                left.value, right.value
+            )),
+        },
         Opcode::Eq => eval_native_boolean(&(left.value == right.value)),
         Opcode::NotEq => eval_native_boolean(&(left.value != right.value)),
         Opcode::Lt => eval_native_boolean(&(left.value < right.value)),
         Opcode::Gt => eval_native_boolean(&(left.value > right.value)),
-        _ => new_error(format_args!(
-            "unknown operator: INTEGER {} INTEGER",
-            operator.as_str()
-        )),
+        Opcode::BitAnd => box_it!(Integer {
+            value: left.value & right.value,
+        }),
+        Opcode::BitOr => box_it!(Integer {
+            value: left.value | right.value,
+        }),
+        Opcode::BitXor => box_it!(Integer {
+            value: left.value ^ right.value,
+        }),
+        Opcode::Shl | Opcode::Shr => {
+            if !(0..64).contains(&right.value) {
+                return new_error(format_args!("shift amount out of range: {}", right.value));
+            }
+            let value = match operator {
+                Opcode::Shl => left.value << right.value,
+                _ => left.value >> right.value,
+            };
+            box_it!(Integer { value })
+        }
+        _ => new_error_of(
+            ErrorKind::UnknownOperator,
+            format_args!("unknown operator: INTEGER {} INTEGER", operator.as_str()),
+        ),
     }
 }
 
@@ -250,18 +555,21 @@ fn eval_boolean_infix_expression(
         match operator {
             Opcode::Eq => eval_native_boolean(&(left_bool.value == right_bool.value)),
             Opcode::NotEq => eval_native_boolean(&(left_bool.value != right_bool.value)),
-            _ => new_error(format_args!(
-                "unknown operator: BOOLEAN {} BOOLEAN",
-                operator.as_str()
-            )),
+            _ => new_error_of(
+                ErrorKind::UnknownOperator,
+                format_args!("unknown operator: BOOLEAN {} BOOLEAN", operator.as_str()),
+            ),
         }
     } else {
-        new_error(format_args!(
-            "unknown operator: {} {} {}",
-            left.object_type().as_str(),
-            operator.as_str(),
-            right.object_type().as_str()
-        ))
+        new_error_of(
+            ErrorKind::UnknownOperator,
+            format_args!(
+                "unknown operator: {} {} {}",
+                left.object_type().as_str(),
+                operator.as_str(),
+                right.object_type().as_str()
+            ),
+        )
     }
 }
 
@@ -278,10 +586,14 @@ fn eval_string_infix_expression(
                 value: format!("{}{}", left_str, right_str),
             })
         }
-        _ => new_error(format_args!(
-            "unknown operator: STRING {} STRING",
-            operator.as_str()
-        )),
+        Opcode::Eq => eval_native_boolean(&(left.value == right.value)),
+        Opcode::NotEq => eval_native_boolean(&(left.value != right.value)),
+        Opcode::Lt => eval_native_boolean(&(left.value < right.value)),
+        Opcode::Gt => eval_native_boolean(&(left.value > right.value)),
+        _ => new_error_of(
+            ErrorKind::UnknownOperator,
+            format_args!("unknown operator: STRING {} STRING", operator.as_str()),
+        ),
     }
 }
 
@@ -289,32 +601,30 @@ fn eval_prefix_expression(operator: &Opcode, right: &ObjectRef) -> ObjectRef {
     match operator {
         Opcode::Bang => eval_bang_operator_expression(right),
         Opcode::Sub => match downcast_ref!(right, Integer) {
-            Some(integer) => box_it!(Integer {
-                value: -integer.value,
-            }),
-            _ => new_error(format_args!(
-                "unknown operator: -{}",
-                right.object_type().as_str()
-            )),
+            Some(integer) => match integer.value.checked_neg() {
+                Some(value) => box_it!(Integer { value }),
+                None => new_error(format_args!("integer overflow: -({})", integer.value)),
+            },
+            _ => new_error_of(
+                ErrorKind::UnknownOperator,
+                format_args!("unknown operator: -{}", right.object_type().as_str()),
+            ),
         },
-        _ => new_error(format_args!(
-            "unknown operator: {}{}",
-            operator.as_str(),
-            right.object_type().as_str()
-        )),
+        _ => new_error_of(
+            ErrorKind::UnknownOperator,
+            format_args!(
+                "unknown operator: {}{}",
+                operator.as_str(),
+                right.object_type().as_str()
+            ),
+        ),
     }
 }
 
 fn eval_bang_operator_expression(right: &ObjectRef) -> ObjectRef {
     match downcast_ref!(right, Boolean) {
-        Some(boolean) => {
-            if boolean.value {
-                box_it!(Boolean { value: false })
-            } else {
-                box_it!(Boolean { value: true })
-            }
-        }
-        _ => box_it!(Boolean { value: false }),
+        Some(boolean) => eval_native_boolean(&!boolean.value),
+        _ => FALSE.with(ObjectRef::clone),
     }
 }
 
@@ -324,38 +634,70 @@ fn eval_identifier_expression(name: &str, env: &Environment) -> ObjectRef {
     }
     match env.get(name) {
         Some(value) => value,
-        None => new_error(format_args!("identifier not found: {}", name)),
+        None => new_error_of(
+            ErrorKind::UnknownIdentifier,
+            format_args!("identifier not found: {}", name),
+        ),
     }
 }
 
-fn is_truthy(object: &ObjectRef) -> bool {
+pub fn is_truthy(object: &ObjectRef) -> bool {
     if let Some(boolean) = downcast_ref!(object, Boolean) {
         return boolean.value;
     }
-    match downcast_ref!(object, Null) {
-        Some(_) => false,
-        _ => true,
-    }
+    downcast_ref!(object, Null).is_none()
+}
+
+pub fn null() -> ObjectRef {
+    NULL.with(ObjectRef::clone)
 }
 
-// TODO: TRUE, FALSE, NULLは使い回しできるようにする
-fn eval_native_boolean(input: &bool) -> ObjectRef {
+pub fn eval_native_boolean(input: &bool) -> ObjectRef {
     if *input {
-        box_it!(Boolean { value: true })
+        TRUE.with(ObjectRef::clone)
     } else {
-        box_it!(Boolean { value: false })
+        FALSE.with(ObjectRef::clone)
     }
 }
 
 pub fn new_error(args: fmt::Arguments) -> ObjectRef {
-    let message = format!("{}", args);
-    box_it!(Error { message })
+    new_error_of(ErrorKind::Other, args)
+}
+
+pub fn new_error_of(kind: ErrorKind, args: fmt::Arguments) -> ObjectRef {
+    box_it!(Error {
+        message: format!("{}", args),
+        kind,
+        line: None
+    })
+}
+
+// Stamps an error raised while evaluating a node with the node's line, unless a node nested
+// inside it already did.
+fn locate(result: ObjectRef, span: Option<Span>) -> ObjectRef {
+    match (downcast_ref!(result, Error), span) {
+        (Some(error), Some(span)) if error.line.is_none() => box_it!(Error {
+            message: error.message.clone(),
+            kind: error.kind,
+            line: Some(span.line),
+        }),
+        _ => result,
+    }
+}
+
+// A `return` inside e.g. an `if` used as an array element yields a ReturnValue; collections and
+// arguments only ever hold the value it wraps.
+fn unwrap_return_value(object: ObjectRef) -> ObjectRef {
+    match downcast_ref!(object, ReturnValue) {
+        Some(return_value) => return_value.value.clone(),
+        None => object,
+    }
 }
 
 fn eval_expressions(expressions: &[Box<Expr>], env: &mut Environment) -> Vec<ObjectRef> {
     let mut result = Vec::new();
     for expr in expressions {
-        let evaluated = eval(expr.as_ref(), env);
+        let evaluated = unwrap_return_value(eval(expr.as_ref(), env));
         if is_error(&evaluated) {
             return vec![evaluated];
         }
@@ -364,73 +706,192 @@ fn eval_expressions(expressions: &[Box<Expr>], env: &mut Environment) -> Vec<Obj
     result
 }
 
-fn apply_function(function: ObjectRef, args: &[ObjectRef]) -> ObjectRef {
+// Evaluates the arguments of a call to `function`. The first error among them is returned
+// instead, unless `function` is a builtin that takes errors as arguments.
+fn eval_arguments(
+    function: &ObjectRef,
+    arguments: &[Box<Expr>],
+    env: &mut Environment,
+) -> Result<Vec<ObjectRef>, ObjectRef> {
+    if downcast_ref!(function, Builtin).is_some_and(|builtin| builtin.takes_errors) {
+        return Ok(arguments
+            .iter()
+            .map(|arg| unwrap_return_value(eval(arg.as_ref(), env)))
+            .collect());
+    }
+    let args = eval_expressions(arguments, env);
+    if args.len() == 1 && is_error(&args[0]) {
+        return Err(args[0].clone());
+    }
+    Ok(args)
+}
+
+// Calls a Monkey function, builtin or bound method with `args`. Functions carry the environment
+// they close over, so builtins can call back into the evaluator without any extra context.
+pub fn apply_function(function: ObjectRef, args: &[ObjectRef]) -> ObjectRef {
     if let Some(builtin) = downcast_ref!(function, Builtin) {
         return (builtin.func)(args.to_vec());
     }
 
+    if let Some(bound) = downcast_ref!(function, BoundFunction) {
+        let mut bound_args = vec![bound.receiver.clone()];
+        bound_args.extend_from_slice(args);
+        return apply_function(bound.function.clone(), &bound_args);
+    }
+
     if let Some(func) = downcast_ref!(function, Function) {
+        let depth = CALL_DEPTH.with(Cell::get);
+        if depth >= func.env.config().max_call_depth {
+            return new_error(format_args!("maximum recursion depth exceeded"));
+        }
+        CALL_DEPTH.with(|d| d.set(depth + 1));
+        let evaluated = stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || {
+            call_function(function.clone(), args.to_vec())
+        });
+        CALL_DEPTH.with(|d| d.set(depth));
+        return evaluated;
+    }
+
+    new_error_of(
+        ErrorKind::TypeMismatch,
+        format_args!("not a function: {:?}", function.object_type().as_str()),
+    )
+}
+
+// Evaluates the body of `function`, which must be a `Function`, with `args` bound to its
+// parameters. When the body ends in `return g(...)` and `g` is a Monkey function too, `g` is
+// called by going around the loop rather than recursing, so tail-recursive functions run in
+// constant stack space. That's skipped while an observer is set, so it still sees every call.
+fn call_function(mut function: ObjectRef, mut args: Vec<ObjectRef>) -> ObjectRef {
+    // Errors escaping a looped call are attributed to the first tail call, as they would be
+    // without the loop.
+    let mut tail_span = None;
+    let result = 'call: loop {
+        let func = downcast_ref!(function, Function).expect("tail calls only loop on functions");
         let mut extended_env = Environment::new_enclosed(&func.env);
         for (param, arg) in func.parameters.iter().zip(args.iter()) {
-            if let Expr::Identifier(name) = param.as_ref() {
+            if let Expr::Identifier(name, _) = param.as_ref() {
                 extended_env.set(name.clone(), arg.clone());
             } else {
-                return new_error(format_args!("invalid parameter: {:?}", param));
+                break 'call new_error(format_args!("invalid parameter: {:?}", param));
             }
         }
-        let evaluated = eval(func.body.as_ref(), &mut extended_env);
-        if let Some(return_value) = downcast_ref!(evaluated, ReturnValue) {
-            return return_value.value.clone();
+
+        let TailCall {
+            init,
+            callee,
+            arguments,
+            span,
+        } = match split_tail_call(&func.body) {
+            Some(tail_call) if !extended_env.is_observed() => tail_call,
+            _ => break unwrap_return_value(eval(func.body.as_ref(), &mut extended_env)),
+        };
+        for stmt in init {
+            let result = eval(stmt.as_ref(), &mut extended_env);
+            if downcast_ref!(result, ReturnValue).is_some() || is_error(&result) {
+                break 'call unwrap_return_value(result);
+            }
         }
-        return evaluated;
-    }
 
-    new_error(format_args!(
-        "not a function: {:?}",
-        function.object_type().as_str()
-    ))
+        let callee = eval(callee, &mut extended_env);
+        if is_error(&callee) {
+            break callee;
+        }
+        let next_args = match eval_arguments(&callee, arguments, &mut extended_env) {
+            Ok(args) => args,
+            Err(error) => break error,
+        };
+        tail_span = tail_span.or(Some(span));
+        if downcast_ref!(callee, Function).is_none() {
+            break apply_function(callee, &next_args);
+        }
+        function = callee;
+        args = next_args;
+    };
+    locate(result, tail_span)
+}
+
+// A function body ending in `return f(...)`, split into the statements before it and the parts
+// of the call.
+struct TailCall<'a> {
+    init: &'a [Box<Stmt>],
+    callee: &'a Expr,
+    arguments: &'a [Box<Expr>],
+    span: Span,
+}
+
+fn split_tail_call(body: &Stmt) -> Option<TailCall<'_>> {
+    let Stmt::Block { statements } = body else {
+        return None;
+    };
+    let (last, init) = statements.split_last()?;
+    match last.as_ref() {
+        Stmt::Return { return_value } => match return_value.as_ref() {
+            Expr::Call {
+                function,
+                arguments,
+                span,
+            } => Some(TailCall {
+                init,
+                callee: function.as_ref(),
+                arguments: arguments.as_slice(),
+                span: *span,
+            }),
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
-fn eval_index_expression(left: &ObjectRef, index: &ObjectRef) -> ObjectRef {
+fn eval_index_expression(left: &ObjectRef, index: &ObjectRef, config: EvalConfig) -> ObjectRef {
     if let (Some(array), Some(integer)) =
         (downcast_ref!(left, Array), downcast_ref!(index, Integer))
     {
-        let idx = integer.value as usize;
-        let max = array.elements.len() - 1;
-        if idx >= array.elements.len() || idx > max {
-            return box_it!(Null);
+        // Negative indices count back from the end, so `arr[-1]` is the last element.
+        let len = array.elements.len() as i64;
+        let idx = if integer.value < 0 {
+            integer.value + len
+        } else {
+            integer.value
+        };
+        if idx < 0 || idx >= len {
+            return match config.index_out_of_range {
+                IndexOutOfRange::Null => null(),
+                IndexOutOfRange::Error => new_error_of(
+                    ErrorKind::IndexOutOfRange,
+                    format_args!("index out of range: {} (length {})", integer.value, len),
+                ),
+            };
         }
-        array.elements[idx].clone()
+        array.elements[idx as usize].clone()
     } else if let Some(hash) = downcast_ref!(left, Hash) {
         eval_hash_index_expression(hash, index)
     } else {
-        new_error(format_args!(
-            "index operator not supported: {}[{}]",
-            left.object_type().as_str(),
-            index.object_type().as_str()
-        ))
+        new_error_of(
+            ErrorKind::UnknownOperator,
+            format_args!(
+                "index operator not supported: {}[{}]",
+                left.object_type().as_str(),
+                index.object_type().as_str()
+            ),
+        )
     }
 }
 
 fn eval_hash_literal(pairs: &[(Box<Expr>, Box<Expr>)], env: &mut Environment) -> ObjectRef {
-    let mut hash = std::collections::HashMap::new();
+    let mut hash = HashPairs::default();
     for (key_expr, value_expr) in pairs {
-        let key = eval(key_expr.as_ref(), env);
+        let key = unwrap_return_value(eval(key_expr.as_ref(), env));
         if is_error(&key) {
             return key;
         }
 
-        let hash_key = if let Some(integer) = downcast_ref!(&key, Integer) {
-            integer.hash_key()
-        } else if let Some(boolean) = downcast_ref!(&key, Boolean) {
-            boolean.hash_key()
-        } else if let Some(string) = downcast_ref!(&key, StringObj) {
-            string.hash_key()
-        } else {
-            return new_error(format_args!("unusable as hash key: {:?}", key.inspect()));
+        let hash_key = match hash_key_of(&key) {
+            Ok(hash_key) => hash_key,
+            Err(e) => return e,
         };
 
-        let value = eval(value_expr.as_ref(), env);
+        let value = unwrap_return_value(eval(value_expr.as_ref(), env));
         if is_error(&value) {
             return value;
         }
@@ -442,21 +903,31 @@ fn eval_hash_literal(pairs: &[(Box<Expr>, Box<Expr>)], env: &mut Environment) ->
     box_it!(Hash { pairs: hash })
 }
 
-fn eval_hash_index_expression(hash: &Hash, index: &ObjectRef) -> ObjectRef {
-    let key = if let Some(integer) = downcast_ref!(index, Integer) {
-        integer.hash_key()
-    } else if let Some(boolean) = downcast_ref!(index, Boolean) {
-        boolean.hash_key()
-    } else if let Some(string) = downcast_ref!(index, StringObj) {
-        string.hash_key()
+pub fn hash_key_of(key: &ObjectRef) -> Result<HashKey, ObjectRef> {
+    if let Some(integer) = downcast_ref!(key, Integer) {
+        Ok(integer.hash_key())
+    } else if let Some(boolean) = downcast_ref!(key, Boolean) {
+        Ok(boolean.hash_key())
+    } else if let Some(string) = downcast_ref!(key, StringObj) {
+        Ok(string.hash_key())
     } else {
-        return new_error(format_args!("unusable as hash key: {:?}", index.inspect()));
+        Err(new_error_of(
+            ErrorKind::TypeMismatch,
+            format_args!("unusable as hash key: {:?}", key.inspect()),
+        ))
+    }
+}
+
+fn eval_hash_index_expression(hash: &Hash, index: &ObjectRef) -> ObjectRef {
+    let key = match hash_key_of(index) {
+        Ok(key) => key,
+        Err(e) => return e,
     };
 
     if let Some(pair) = hash.pairs.get(&key) {
         pair.value.clone()
     } else {
-        box_it!(Null)
+        null()
     }
 }
 
@@ -464,6 +935,7 @@ fn eval_hash_index_expression(hash: &Hash, index: &ObjectRef) -> ObjectRef {
 mod tests {
     use super::*;
     use crate::{object::Function, object::Object, parser::parse_program};
+    use std::rc::Rc;
 
     fn assert_is_integer(object: &ObjectRef, expected_value: i64) {
         if let Some(integer) = downcast_ref!(object, Integer) {
@@ -497,6 +969,46 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_eval_bitwise_expression() {
+        let tests = vec![
+            ("1 << 4;", 16),
+            ("256 >> 4;", 16),
+            ("-16 >> 2;", -4),
+            ("6 & 3;", 2),
+            ("6 | 3;", 7),
+            ("6 ^ 3;", 5),
+            ("1 << 2 + 1;", 8),
+            ("1 | 2 & 3;", 3),
+            ("1 ^ 3 & 1;", 0),
+            ("1 << 63;", i64::MIN),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_is_integer(&results, expected);
+        }
+
+        let tests = vec![
+            ("1 << 64;", "shift amount out of range: 64 (line 1)"),
+            ("1 >> -1;", "shift amount out of range: -1 (line 1)"),
+            (
+                "true & false;",
+                "unknown operator: BOOLEAN & BOOLEAN (line 1)",
+            ),
+            ("1 | true;", "type mismatch: INTEGER | BOOLEAN (line 1)"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected);
+        }
+    }
+
     #[test]
     fn test_eval_boolean_expression() {
         let tests = vec![
@@ -550,6 +1062,44 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_shared_singletons() {
+        let tests = vec![
+            (
+                "true;",
+                vec!["1 < 2;", "!false;", "contains([1], 1);", "is_prime(7);"],
+            ),
+            (
+                "false;",
+                vec![
+                    "1 == 2;",
+                    "!5;",
+                    "bit(4, 0);",
+                    "all([1, 2], fn(x) { x > 1; });",
+                ],
+            ),
+            (
+                "if (false) { 1; };",
+                vec!["[][0];", "{}[1];", "first([]);", "last([]);"],
+            ),
+        ];
+
+        for (first, others) in tests {
+            let mut env = Environment::new();
+            let shared = eval_program(&parse_program(first).unwrap(), &mut env).unwrap();
+            for input in others {
+                let program = parse_program(input).unwrap();
+                let results = eval_program(&program, &mut env).unwrap();
+                assert!(Rc::ptr_eq(&results, &shared), "{}", input);
+            }
+        }
+
+        let t = eval_native_boolean(&true);
+        assert!(downcast_ref!(t, Boolean).unwrap().value);
+        assert_eq!(t.inspect(), "true");
+        assert_eq!(null().inspect(), "null");
+    }
+
     #[test]
     fn test_eval_if_expression() {
         let tests = vec![
@@ -561,6 +1111,13 @@ mod tests {
             ("if (1 > 2) { 10; };", None),
             ("if (1 > 2) { 10; } else { 20; };", Some(20)),
             ("if (1 < 2) { 10; } else { 20; };", Some(10)),
+            ("if (1 > 2) { 10; } else if (2 > 1) { 20; } else { 30; };", Some(20)),
+            ("if (1 > 2) { 10; } else if (2 > 3) { 20; } else { 30; };", Some(30)),
+            ("if (1 > 2) { 10; } else if (2 > 3) { 20; };", None),
+            (
+                "let x = 3; if (x < 1) { 1; } else if (x < 2) { 2; } else if (x < 4) { 3; } else { 4; };",
+                Some(3),
+            ),
         ];
 
         for (input, expected) in tests {
@@ -577,6 +1134,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_if_expression_values() {
+        let tests = vec![
+            ("let x = if (false) { 10; }; x;", "null"),
+            ("let x = if (true) { 10; }; x;", "10"),
+            ("[if (false) { 1; }, if (true) { 2; }];", "[null, 2]"),
+            ("type(if (false) { 1; });", "\"NULL\""),
+            ("let x = if (false) { 1; }; x == first([]);", "true"),
+            (
+                "let n = 0; let hits = 0; while (n < 3) { n = n + 1; if (n == 2) { hits = hits + 1; }; } hits;",
+                "1",
+            ),
+            (
+                "if (1 + true) { 10; };",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+            (
+                "let x = if (unknown) { 1; } else { 2; }; x;",
+                "identifier not found: unknown (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
     #[test]
     fn test_eval_return_statement() {
         let tests = vec![
@@ -604,32 +1191,187 @@ mod tests {
     }
 
     #[test]
-    fn test_error_handling() {
+    fn test_error_line_numbers() {
         let tests = vec![
-            ("5 + true;", "type mismatch: INTEGER + BOOLEAN"),
-            ("5 + true; 5;", "type mismatch: INTEGER + BOOLEAN"),
-            ("-true;", "unknown operator: -BOOLEAN"),
-            ("true + false;", "unknown operator: BOOLEAN + BOOLEAN"),
-            ("5; true + false; 5;", "unknown operator: BOOLEAN + BOOLEAN"),
             (
-                "if (10 > 1) { true + false; };",
-                "unknown operator: BOOLEAN + BOOLEAN",
+                "let a = 1;\nlet b = x + a;",
+                "identifier not found: x (line 2)",
             ),
             (
-                "
-                if (10 > 1) {
+                "let a = 1;\n\n\na + true;",
+                "type mismatch: INTEGER + BOOLEAN (line 4)",
+            ),
+            // Errors inside a function point at the body, not the call site.
+            (
+                "let f = fn() {\n  missing;\n};\nf();",
+                "identifier not found: missing (line 2)",
+            ),
+            (
+                "const c = 1;\nc = 2;",
+                "cannot assign to constant c (line 2)",
+            ),
+            (
+                "1;\nlen(1, 2);",
+                "wrong number of arguments. got=2, want=1 (line 2)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+            assert_eq!(
+                downcast_ref!(results, Error).unwrap().line,
+                expected[expected.rfind(' ').unwrap() + 1..expected.len() - 1]
+                    .parse()
+                    .ok()
+            );
+        }
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let tests = vec![
+            ("5 + true;", ErrorKind::TypeMismatch),
+            ("-true;", ErrorKind::UnknownOperator),
+            ("[1] < [2];", ErrorKind::UnknownOperator),
+            ("foobar;", ErrorKind::UnknownIdentifier),
+            ("foobar = 1;", ErrorKind::UnknownIdentifier),
+            ("1 / 0;", ErrorKind::DivideByZero),
+            ("len(1, 2);", ErrorKind::Arity),
+            ("len(1);", ErrorKind::Argument),
+            ("first(1);", ErrorKind::Argument),
+            ("const a = 1; a = 2;", ErrorKind::Constant),
+            ("{[1]: 2};", ErrorKind::TypeMismatch),
+            ("let f = fn() { 1 + true; }; f();", ErrorKind::TypeMismatch),
+            ("1 << 64;", ErrorKind::Other),
+        ];
+
+        let error_kind = get_builtin("error_kind").unwrap();
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(
+                downcast_ref!(results, Error).map(|e| e.kind),
+                Some(expected),
+                "{}",
+                input
+            );
+            assert_eq!(
+                (error_kind.func)(vec![results]).inspect(),
+                format!("{:?}", expected.as_str()),
+                "{}",
+                input
+            );
+        }
+
+        let non_error: ObjectRef = box_it!(Integer { value: 1 });
+        assert_eq!((error_kind.func)(vec![non_error]).inspect(), "null");
+        assert_eq!(
+            (error_kind.func)(vec![]).inspect(),
+            "wrong number of arguments. got=0, want=1"
+        );
+    }
+
+    #[test]
+    fn test_builtin_error_kind() {
+        let tests = vec![
+            ("error_kind(1 / 0);", "\"DIVIDE_BY_ZERO\""),
+            ("error_kind(missing);", "\"UNKNOWN_IDENTIFIER\""),
+            ("error_kind(len(1, 2));", "\"ARITY\""),
+            ("error_kind(1 + 1);", "null"),
+            ("error_kind(error(\"bad input\"));", "\"USER\""),
+            ("error(\"bad input\");", "bad input (line 1)"),
+            (
+                "error(1);",
+                "argument to `error` must be STRING, got INTEGER (line 1)",
+            ),
+            (
+                "let f = fn(x) { 10 / x; }; error_kind(f(0)) == \"DIVIDE_BY_ZERO\";",
+                "true",
+            ),
+            (
+                "let safe_div = fn(a, b) { if (error_kind(a / b)) { 0; } else { a / b; }; };\n\
+                 [safe_div(6, 3), safe_div(1, 0)];",
+                "[2, 0]",
+            ),
+            (
+                "let kind = fn(x) { return error_kind(x[0]); }; kind(1);",
+                "\"UNKNOWN_OPERATOR\"",
+            ),
+            // Other calls still stop at an error argument.
+            ("len(1 / 0);", "division by zero (line 1)"),
+            (
+                "error_kind(1 / 0, 2);",
+                "wrong number of arguments. got=2, want=1 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_error_handling() {
+        let tests = vec![
+            ("5 + true;", "type mismatch: INTEGER + BOOLEAN (line 1)"),
+            ("1 / 0;", "division by zero (line 1)"),
+            ("let z = 0;\n10 / z;", "division by zero (line 2)"),
+            (
+                "(-9223372036854775807 - 1) / -1;",
+                "integer overflow: -9223372036854775808 / -1 (line 1)",
+            ),
+            (
+                "9223372036854775807 + 1;",
+                "integer overflow: 9223372036854775807 + 1 (line 1)",
+            ),
+            (
+                "-9223372036854775807 - 2;",
+                "integer overflow: -9223372036854775807 - 2 (line 1)",
+            ),
+            (
+                "let big = 4294967296;\nbig * big;",
+                "integer overflow: 4294967296 * 4294967296 (line 2)",
+            ),
+            (
+                "-(-9223372036854775807 - 1);",
+                "integer overflow: -(-9223372036854775808) (line 1)",
+            ),
+            ("5 + true; 5;", "type mismatch: INTEGER + BOOLEAN (line 1)"),
+            ("-true;", "unknown operator: -BOOLEAN (line 1)"),
+            (
+                "true + false;",
+                "unknown operator: BOOLEAN + BOOLEAN (line 1)",
+            ),
+            (
+                "5; true + false; 5;",
+                "unknown operator: BOOLEAN + BOOLEAN (line 1)",
+            ),
+            (
+                "if (10 > 1) { true + false; };",
+                "unknown operator: BOOLEAN + BOOLEAN (line 1)",
+            ),
+            (
+                "
+                if (10 > 1) {
                     if (10 > 1) {
                         return true + false;
                     };
                     return 1;
                 };
                 ",
-                "unknown operator: BOOLEAN + BOOLEAN",
+                "unknown operator: BOOLEAN + BOOLEAN (line 4)",
             ),
-            ("foobar;", "identifier not found: foobar"),
+            ("foobar;", "identifier not found: foobar (line 1)"),
             (
                 "\"Hello\" - \"World\";",
-                "unknown operator: STRING - STRING",
+                "unknown operator: STRING - STRING (line 1)",
             ),
         ];
 
@@ -639,7 +1381,7 @@ mod tests {
             let results = eval_program(&program, &mut env);
             match results {
                 Ok(result) => {
-                    if let Some(_) = downcast_ref!(result, Error) {
+                    if downcast_ref!(result, Error).is_some() {
                         assert_eq!(result.inspect(), expected);
                     } else {
                         panic!("Expected error object");
@@ -667,6 +1409,262 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_while_statement() {
+        let input = "
+        let i = 1;
+        let sum = 0;
+        while (i < 11) {
+            let sum = sum + i;
+            let i = i + 1;
+        }
+        sum;
+        ";
+        let program = parse_program(input).unwrap();
+        let mut env = Environment::new();
+        let results = eval_program(&program, &mut env).unwrap();
+        assert_is_integer(&results, 55);
+
+        let tests = vec![
+            ("while (false) { 1; }", "null"),
+            ("let f = fn() { while (true) { return 5; } }; f();", "5"),
+            (
+                "while (true) { 1 + true; }",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+            ("while (x) { 1; }", "identifier not found: x (line 1)"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected);
+        }
+    }
+
+    #[test]
+    fn test_let_in_expression() {
+        let tests = vec![
+            ("let x = 5 in x * 2;", "10"),
+            ("(let x = 5 in x) + 1;", "6"),
+            ("let y = let x = 5 in x * 2; y;", "10"),
+            ("let x = 1 in let y = x + 1 in x + y;", "3"),
+            ("let x = 1 in let x = x + 1 in x;", "2"),
+            ("let x = 10; let x = 1 in x;", "1"),
+            ("let x = 10; let x = 1 in x; x;", "10"),
+            ("let x = 1 in x; x;", "identifier not found: x (line 1)"),
+            ("let f = fn(n) { let m = n * 2 in m + 1; }; f(3);", "7"),
+            (
+                "let x = 1 + true in x;",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_with_expression() {
+        let tests = vec![
+            ("with (x = 1, y = 2) { x + y; };", "3"),
+            ("with (x = 1, y = x + 1) { y; };", "2"),
+            ("let x = 10; with (x = 1) { x; };", "1"),
+            ("let x = 10; with (x = 1) { x; }; x;", "10"),
+            (
+                "with (x = 1) { let y = 2; }; y;",
+                "identifier not found: y (line 1)",
+            ),
+            (
+                "with (x = 1) { x; }; x;",
+                "identifier not found: x (line 1)",
+            ),
+            ("let z = 5; with () { z; };", "5"),
+            (
+                "with (x = 1 + true) { x; };",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+            ("let f = fn() { with (x = 1) { return x; }; 2; }; f();", "1"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_try_finally() {
+        let tests = vec![
+            ("try { 1; } finally { 2; };", "1"),
+            (
+                "let a = 0; try { a = 1; } finally { a = a + 10; }; a;",
+                "11",
+            ),
+            // The cleanup runs, and the body's error is still the result.
+            (
+                "let a = 0; try { a = 1; missing; a = 2; } finally { a = a + 10; };",
+                "identifier not found: missing (line 1)",
+            ),
+            (
+                "try { missing; } finally { 1 + true; };",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+            (
+                "try { 1; } finally { 1 + true; };",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+            // The returned value is taken before the cleanup runs.
+            (
+                "let a = 0; let f = fn() { try { return a; } finally { a = 5; }; }; f() + a;",
+                "5",
+            ),
+            (
+                "let f = fn() { try { return 1; } finally { return 2; }; }; f();",
+                "2",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+
+        // The program stops at the error, but the cleanup has already run by then.
+        let mut env = Environment::new();
+        let program = parse_program("let a = 0; try { missing; } finally { a = 10; }; a = 20;");
+        let results = eval_program(&program.unwrap(), &mut env).unwrap();
+        assert!(is_error(&results));
+        assert_eq!(env.get("a").unwrap().inspect(), "10");
+    }
+
+    #[test]
+    fn test_return_value_not_stored_in_collections() {
+        let tests = vec![
+            ("[if (true) { return 1; }, 2];", "[1, 2]"),
+            ("{\"a\": if (true) { return 1; }};", "{\"a\": 1}"),
+            ("{if (true) { return \"k\"; }: 2};", "{\"k\": 2}"),
+            ("len([with () { return 1; }]);", "1"),
+            ("let f = fn(x) { x; }; f(if (true) { return 3; }) + 1;", "4"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+
+        let program = parse_program("[if (true) { return 1; }];").unwrap();
+        let mut env = Environment::new();
+        let results = eval_program(&program, &mut env).unwrap();
+        let array = downcast_ref!(&results, Array).unwrap();
+        assert!(downcast_ref!(array.elements[0], ReturnValue).is_none());
+
+        let program = parse_program("{1: if (true) { return 1; }};").unwrap();
+        let mut env = Environment::new();
+        let results = eval_program(&program, &mut env).unwrap();
+        let hash = downcast_ref!(&results, Hash).unwrap();
+        assert!(hash
+            .pairs
+            .values()
+            .all(|pair| downcast_ref!(pair.value, ReturnValue).is_none()));
+    }
+
+    #[test]
+    fn test_let_destructure_statements() {
+        let tests = vec![
+            ("let [a, b, c] = [1, 2, 3]; a + b * c;", "7"),
+            ("let [a, _, c] = [1, 2, 3]; [a, c];", "[1, 3]"),
+            ("let [x] = [[1, 2]]; x;", "[1, 2]"),
+            ("let [] = []; 1;", "1"),
+            ("let [a, b] = [1, 2];", "[1, 2]"),
+            (
+                "let [a, _] = [1, 2]; _;",
+                "identifier not found: _ (line 1)",
+            ),
+            (
+                "let [a, b] = [1, 2, 3];",
+                "cannot destructure array of length 3 into 2 names (line 1)",
+            ),
+            (
+                "let [a, b, c] = [1];",
+                "cannot destructure array of length 1 into 3 names (line 1)",
+            ),
+            (
+                "let [a] = 1;",
+                "cannot destructure INTEGER as ARRAY (line 1)",
+            ),
+            (
+                "const b = 1; let [a, b] = [2, 3]; a;",
+                "cannot assign to constant b (line 1)",
+            ),
+            (
+                "let [a] = [1 + true];",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_const_and_assign_statements() {
+        let tests = vec![
+            ("const PI = 3; PI;", "3"),
+            ("let a = 1; a = 2; a;", "2"),
+            ("let a = 1; a = a + 1;", "2"),
+            (
+                "let i = 0; let sum = 0; while (i < 5) { i = i + 1; sum = sum + i; } sum;",
+                "15",
+            ),
+            (
+                "const PI = 3; PI = 4;",
+                "cannot assign to constant PI (line 1)",
+            ),
+            (
+                "const PI = 3; PI = 4; PI;",
+                "cannot assign to constant PI (line 1)",
+            ),
+            (
+                "const PI = 3; let PI = 4;",
+                "cannot assign to constant PI (line 1)",
+            ),
+            (
+                "const PI = 3; const PI = 4;",
+                "cannot assign to constant PI (line 1)",
+            ),
+            ("const PI = 3; let f = fn() { let PI = 4; PI; }; f();", "4"),
+            ("const PI = 3; let f = fn(PI) { PI; }; f(4);", "4"),
+            ("x = 1;", "identifier not found: x (line 1)"),
+            (
+                "let a = 1; a = 1 + true;",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
     #[test]
     fn test_function_object() {
         let input = "fn(x) { x + 2; };";
@@ -691,6 +1689,9 @@ mod tests {
             ("let add = fn(x, y) { x + y; }; add(5, 5);", 10),
             ("let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));", 20),
             ("fn(x) { x; }(5);", 5),
+            ("let getFn = fn() { fn(x) { x * 2; }; }; getFn()(3);", 6),
+            ("let fns = [fn(x) { x + 1; }]; fns[0](1);", 2),
+            ("let add = fn(x) { fn(y) { x + y; }; }; add(1)(2);", 3),
         ];
 
         for (input, expected) in tests {
@@ -717,148 +1718,2210 @@ mod tests {
     }
 
     #[test]
-    fn test_string_literal() {
-        let input = "\"Hello, World!\";";
-        let program = parse_program(input).unwrap();
-        let mut env = Environment::new();
-        let results = eval_program(&program, &mut env).unwrap();
-        if let Some(string) = downcast_ref!(&results, StringObj) {
-            assert_eq!(string.inspect(), "\"Hello, World!\"");
-        } else {
-            panic!("Expected String object");
+    fn test_duplicate_parameters() {
+        let tests = vec![
+            ("fn(x, x) { x; };", "duplicate parameter: x"),
+            (
+                "let f = fn(a, b, a) { a; }; f(1, 2, 3);",
+                "duplicate parameter: a (line 1)",
+            ),
+            ("let f = fn(x) { fn(x) { x; }; }; f(1)(2);", "2"),
+            ("fn(x, y) { x + y; }(1, 2);", "3"),
+            ("fn(_, _) { 1; }(2, 3);", "1"),
+            ("fn(_, x, _) { x; }(1, 2, 3);", "2"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
         }
     }
 
     #[test]
-    fn test_string_concatenation() {
-        let input = "\"Hello\" + \" \" + \"World!\";";
-        let program = parse_program(input).unwrap();
-        let mut env = Environment::new();
-        let results = eval_program(&program, &mut env).unwrap();
-        if let Some(string) = downcast_ref!(&results, StringObj) {
-            assert_eq!(string.inspect(), "\"Hello World!\"");
-        } else {
-            panic!("Expected String object");
+    fn test_deep_recursion() {
+        let tests = vec![
+            (
+                "let fib = fn(n) { if (n < 2) { n; } else { fib(n - 1) + fib(n - 2); }; }; fib(18);",
+                "2584",
+            ),
+            (
+                "let sum = fn(n) { if (n == 0) { 0; } else { n + sum(n - 1); }; }; sum(100);",
+                "5050",
+            ),
+            (
+                "let adders = fn(n) { if (n == 0) { []; } else { push(adders(n - 1), fn(x) { x + n; }); }; }; let fs = adders(50); [fs[0](1), fs[49](1)];",
+                "[2, 51]",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_closures_share_captured_environment() {
+        let tests = vec![
+            (
+                "let counter = fn() { let n = 0; fn() { n = n + 1; n; }; }; let next = counter(); next(); next(); next();",
+                "3",
+            ),
+            (
+                "let c1 = fn() { let n = 0; fn() { n = n + 1; n; }; }(); let c2 = c1; c1(); c2();",
+                "2",
+            ),
+            ("let count = 0; let inc = fn() { count = count + 1; }; inc(); inc(); count;", "2"),
+            ("let x = 1; let f = fn() { x; }; let x = 2; f();", "2"),
+            (
+                "let fact = fn(n) { if (n < 2) { 1; } else { n * fact(n - 1); }; }; fact(5);",
+                "120",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_string_literal() {
+        let input = "\"Hello, World!\";";
+        let program = parse_program(input).unwrap();
+        let mut env = Environment::new();
+        let results = eval_program(&program, &mut env).unwrap();
+        if let Some(string) = downcast_ref!(&results, StringObj) {
+            assert_eq!(string.inspect(), "\"Hello, World!\"");
+        } else {
+            panic!("Expected String object");
+        }
+
+        let input = r#"let s = "say \"hi\""; [s, len(s)];"#;
+        let program = parse_program(input).unwrap();
+        let mut env = Environment::new();
+        let results = eval_program(&program, &mut env).unwrap();
+        let array = downcast_ref!(&results, Array).unwrap();
+        assert_eq!(
+            downcast_ref!(array.elements[0], StringObj).unwrap().value,
+            "say \"hi\""
+        );
+        assert_eq!(array.elements[1].inspect(), "8");
+
+        let input = r#"let s = "a\nb"; [s, len(s)];"#;
+        let program = parse_program(input).unwrap();
+        let mut env = Environment::new();
+        let results = eval_program(&program, &mut env).unwrap();
+        let array = downcast_ref!(&results, Array).unwrap();
+        assert_eq!(
+            downcast_ref!(array.elements[0], StringObj).unwrap().value,
+            "a\nb"
+        );
+        assert_eq!(array.elements[1].inspect(), "3");
+        assert_eq!(results.inspect(), r#"["a\nb", 3]"#);
+    }
+
+    #[test]
+    fn test_string_concatenation() {
+        let input = "\"Hello\" + \" \" + \"World!\";";
+        let program = parse_program(input).unwrap();
+        let mut env = Environment::new();
+        let results = eval_program(&program, &mut env).unwrap();
+        if let Some(string) = downcast_ref!(&results, StringObj) {
+            assert_eq!(string.inspect(), "\"Hello World!\"");
+        } else {
+            panic!("Expected String object");
+        }
+    }
+
+    #[test]
+    fn test_string_comparison() {
+        let tests = vec![
+            ("\"abc\" < \"abd\";", true),
+            ("\"abd\" < \"abc\";", false),
+            ("\"abd\" > \"abc\";", true),
+            ("\"abc\" > \"abc\";", false),
+            ("\"abc\" == \"abc\";", true),
+            ("\"abc\" == \"abd\";", false),
+            ("\"abc\" != \"abd\";", true),
+            ("\"abc\" != \"abc\";", false),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected.to_string());
+        }
+    }
+
+    #[test]
+    fn test_builtin_functions_with_integer() {
+        let tests = vec![
+            ("len(\"\");", 0),
+            ("len(\"four\");", 4),
+            ("len(\"hello world\");", 11),
+            ("len([1,2,3]);", 3),
+            ("len([]);", 0),
+            ("len([1, 2 * 2, 3 + 3]);", 3),
+            ("first([1, 2, 3]);", 1),
+            ("last([1, 2, 3]);", 3),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env);
+            match results {
+                Ok(result) => {
+                    if let Some(integer) = downcast_ref!(&result, Integer) {
+                        assert_eq!(integer.value, expected);
+                    } else {
+                        panic!("Expected Integer object");
+                    }
+                }
+                Err(e) => panic!("Error: {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_builtin_functions_with_slices() {
+        let tests: Vec<(&str, &[i64])> = vec![
+            ("rest([1, 2, 3]);", &[2, 3]),
+            ("push([], 1);", &[1]),
+            ("push([1], 2);", &[1, 2]),
+            ("push([1, 2], 3);", &[1, 2, 3]),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env);
+            match results {
+                Ok(result) => {
+                    if let Some(array) = downcast_ref!(&result, Array) {
+                        for (i, element) in array.elements.iter().enumerate() {
+                            if let Some(integer) = downcast_ref!(element, Integer) {
+                                assert_eq!(integer.value, expected[i]);
+                            } else {
+                                panic!("Expected Integer object");
+                            }
+                        }
+                    } else {
+                        panic!("Expected Array object");
+                    }
+                }
+                Err(e) => panic!("Error: {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_builtin_functions_with_null() {
+        let tests = vec![("first([]);", "null"), ("last([]);", "null")];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env);
+            match results {
+                Ok(result) => {
+                    if let Some(null) = downcast_ref!(&result, Null) {
+                        assert_eq!(null.inspect(), expected);
+                    } else {
+                        panic!("Expected Null object");
+                    }
+                }
+                Err(e) => panic!("Error: {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_builtin_all_and_any() {
+        let tests = vec![
+            ("all([2, 4, 6], fn(x) { x > 1; });", "true"),
+            ("all([2, 4, 6], fn(x) { x > 2; });", "false"),
+            ("all([], fn(x) { false; });", "true"),
+            ("any([1, 2, 3], fn(x) { x > 2; });", "true"),
+            ("any([1, 2, 3], fn(x) { x > 3; });", "false"),
+            ("any([], fn(x) { true; });", "false"),
+            ("all([1, 2], fn(x) { x; });", "true"),
+            (
+                "all([1, 2], fn(x) { x + true; });",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+            (
+                "any([1, 2], fn(x) { x + true; });",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+            // Short-circuits before reaching the element whose predicate errors.
+            ("all([1, true], fn(x) { x + 1 > 5; });", "false"),
+            ("any([1, true], fn(x) { x + 1 > 0; });", "true"),
+            (
+                "all(1, fn(x) { x; });",
+                "argument to `all` must be ARRAY, got INTEGER (line 1)",
+            ),
+            (
+                "any([1], 1);",
+                "argument to `any` must be FUNCTION, got INTEGER (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_inspect_functions_in_collections() {
+        let tests = vec![
+            ("[fn(x, y) { x + y; }, 1];", "[fn(x, y), 1]"),
+            ("[len, [first]];", "[builtin(len), [builtin(first)]]"),
+            ("{\"f\": fn() { 1; }};", "{\"f\": fn()}"),
+            ("[bind(fn(a, b) { a - b; }, 10)];", "[bound fn(a, b)]"),
+            ("let xs = [1]; [fn() { xs; }, xs];", "[fn(), [1]]"),
+            ("fn(x) { x; };", "fn(x) {\n  x\n}"),
+            ("len;", "builtin function"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_bind() {
+        let tests = vec![
+            (
+                "let greet = fn(name) { \"Hello, \" + name; }; bind(greet, \"Bob\")();",
+                "\"Hello, Bob\"",
+            ),
+            ("let sub = fn(a, b) { a - b; }; bind(sub, 10)(3);", "7"),
+            ("bind(len, [1, 2, 3])();", "3"),
+            ("bind(bind(fn(a, b) { a * b; }, 6), 7)();", "42"),
+            (
+                "let add = fn(a, b) { a + b; }; let inc = bind(add, 1); [inc(1), inc(2)];",
+                "[2, 3]",
+            ),
+            (
+                "bind(1, 2);",
+                "argument to `bind` must be FUNCTION, got INTEGER (line 1)",
+            ),
+            (
+                "bind(len);",
+                "wrong number of arguments. got=1, want=2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_type() {
+        let tests = vec![
+            ("type([1, 2]);", "\"ARRAY\""),
+            ("type(1);", "\"INTEGER\""),
+            ("type(\"a\");", "\"STRING\""),
+            ("type(true);", "\"BOOLEAN\""),
+            ("type({});", "\"HASH\""),
+            ("type(if (false) { 1; });", "\"NULL\""),
+            ("type(fn(x) { x; });", "\"FUNCTION\""),
+            ("type(len);", "\"BUILTIN\""),
+            (
+                "type(1, 2);",
+                "wrong number of arguments. got=2, want=1 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_str() {
+        let tests = vec![
+            ("str(5);", "5"),
+            ("str(-5);", "-5"),
+            ("str(true);", "true"),
+            ("str([1, 2]);", "[1, 2]"),
+            ("str(\"a\");", "a"),
+            ("str([\"a\"]);", "[\"a\"]"),
+            ("\"count: \" + str(5);", "count: 5"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            if let Some(string) = downcast_ref!(&results, StringObj) {
+                assert_eq!(string.value, expected, "{}", input);
+            } else {
+                panic!("Expected String object");
+            }
+        }
+
+        let program = parse_program("str(1, 2);").unwrap();
+        let mut env = Environment::new();
+        let results = eval_program(&program, &mut env).unwrap();
+        assert_eq!(
+            results.inspect(),
+            "wrong number of arguments. got=2, want=1 (line 1)"
+        );
+    }
+
+    #[test]
+    fn test_builtin_int() {
+        let tests = vec![
+            ("int(\"42\");", "42"),
+            ("int(\"-7\");", "-7"),
+            ("int(7);", "7"),
+            ("int(\"1\") + 1;", "2"),
+            (
+                "int(\"nope\");",
+                "could not parse \"nope\" as INTEGER (line 1)",
+            ),
+            ("int(\"\");", "could not parse \"\" as INTEGER (line 1)"),
+            (
+                "int(true);",
+                "argument to `int` not supported, got BOOLEAN (line 1)",
+            ),
+            (
+                "int();",
+                "wrong number of arguments. got=0, want=1 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_benchmark() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        fn count(_: Vec<ObjectRef>) -> ObjectRef {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            null()
+        }
+
+        let benchmark = get_builtin("benchmark").unwrap();
+        let thunk: ObjectRef = box_it!(Builtin {
+            name: "count",
+            func: count,
+            takes_errors: false,
+        });
+        let result = (benchmark.func)(vec![box_it!(Integer { value: 25 }), thunk]);
+        assert!(downcast_ref!(result, Integer).unwrap().value >= 0);
+        assert_eq!(CALLS.load(Ordering::SeqCst), 25);
+
+        let tests = vec![
+            (
+                "let sum = fn(n) { let i = 0; while (i < n) { i = i + 1; } i; }; benchmark(3, fn() { sum(100); }) > -1;",
+                "true",
+            ),
+            (
+                "let runs = 0; benchmark(4, fn() { runs = runs + 1; }); runs;",
+                "4",
+            ),
+            (
+                "benchmark(2, fn() { 1 + true; });",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+            (
+                "benchmark(0, fn() { 1; });",
+                "iteration count for `benchmark` must be positive, got 0 (line 1)",
+            ),
+            (
+                "benchmark(\"a\", fn() { 1; });",
+                "argument to `benchmark` must be INTEGER, got STRING (line 1)",
+            ),
+            (
+                "benchmark(1, 1);",
+                "argument to `benchmark` must be FUNCTION, got INTEGER (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_digits() {
+        let tests = vec![
+            ("digits(1234);", "[1, 2, 3, 4]"),
+            ("digits(0);", "[0]"),
+            ("digits(-907);", "[9, 0, 7]"),
+            ("digit_sum(1234);", "10"),
+            ("digit_sum(0);", "0"),
+            ("digit_sum(-99);", "18"),
+            ("digit_sum(-9223372036854775807 - 1);", "89"),
+            (
+                "digits(\"12\");",
+                "argument to `digits` must be INTEGER, got STRING (line 1)",
+            ),
+            (
+                "digit_sum([1]);",
+                "argument to `digit_sum` must be INTEGER, got ARRAY (line 1)",
+            ),
+            (
+                "digits(1, 2);",
+                "wrong number of arguments. got=2, want=1 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_is_prime_and_factors() {
+        let tests = vec![
+            ("is_prime(13);", "true"),
+            ("is_prime(2);", "true"),
+            ("is_prime(97);", "true"),
+            ("is_prime(12);", "false"),
+            ("is_prime(49);", "false"),
+            ("is_prime(1);", "false"),
+            ("is_prime(0);", "false"),
+            ("is_prime(-7);", "false"),
+            ("factors(12);", "[1, 2, 3, 4, 6, 12]"),
+            ("factors(13);", "[1, 13]"),
+            ("factors(36);", "[1, 2, 3, 4, 6, 9, 12, 18, 36]"),
+            ("factors(1);", "[1]"),
+            ("factors(-6);", "[1, 2, 3, 6]"),
+            (
+                "factors(0);",
+                "argument to `factors` must not be 0 (line 1)",
+            ),
+            (
+                "is_prime(true);",
+                "argument to `is_prime` must be INTEGER, got BOOLEAN (line 1)",
+            ),
+            (
+                "factors(\"4\");",
+                "argument to `factors` must be INTEGER, got STRING (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_index() {
+        let tests = vec![
+            ("index([1, 2, 3, 2], 2);", "1"),
+            ("index([1, 2, 3], 4);", "-1"),
+            ("index([], 1);", "-1"),
+            ("index([\"a\", \"b\"], \"b\");", "1"),
+            ("index([true, false], false);", "1"),
+            ("index([1, \"1\", true], \"1\");", "1"),
+            ("index([1, 2], true);", "-1"),
+            ("index([{\"a\": 1}, {\"b\": 2}], {\"b\": 2});", "1"),
+            (
+                "index(\"abc\", \"a\");",
+                "argument to `index` must be ARRAY, got STRING (line 1)",
+            ),
+            (
+                "index([1]);",
+                "wrong number of arguments. got=1, want=2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_binary_search() {
+        let tests = vec![
+            ("binary_search([1, 3, 5, 7], 5);", "2"),
+            ("binary_search([1, 3, 5, 7], 1);", "0"),
+            ("binary_search([1, 3, 5, 7], 7);", "3"),
+            ("binary_search([1, 3, 5, 7], 4);", "-1"),
+            ("binary_search([1, 3, 5, 7], 0);", "-1"),
+            ("binary_search([1, 3, 5, 7], 8);", "-1"),
+            ("binary_search([], 1);", "-1"),
+            ("binary_search([-5, -2, 0], -2);", "1"),
+            (
+                "binary_search([1, \"3\"], 3);",
+                "elements of `binary_search` must be INTEGER, got STRING at index 1 (line 1)",
+            ),
+            (
+                "binary_search([1], \"1\");",
+                "argument to `binary_search` must be INTEGER, got STRING (line 1)",
+            ),
+            (
+                "binary_search(1, 1);",
+                "argument to `binary_search` must be ARRAY, got INTEGER (line 1)",
+            ),
+            (
+                "binary_search([1]);",
+                "wrong number of arguments. got=1, want=2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+
+        // Unsorted input gives some answer rather than panicking.
+        let program = parse_program("binary_search([5, 1, 4, 2, 3], 2);").unwrap();
+        let result = eval_program(&program, &mut Environment::new()).unwrap();
+        assert!(downcast_ref!(result, Integer).is_some());
+    }
+
+    #[test]
+    fn test_builtin_map() {
+        let tests = vec![
+            ("map([1, 2, 3], fn(x) { x * 2; });", "[2, 4, 6]"),
+            ("map([], fn(x) { x * 2; });", "[]"),
+            ("map([[1], [1, 2]], len);", "[1, 2]"),
+            ("let a = [1, 2]; map(a, fn(x) { x + 1; }); a;", "[1, 2]"),
+            (
+                "map([1, true, 3], fn(x) { x * 2; });",
+                "type mismatch: BOOLEAN * INTEGER (line 1)",
+            ),
+            (
+                "map(1, fn(x) { x; });",
+                "argument to `map` must be ARRAY, got INTEGER (line 1)",
+            ),
+            (
+                "map([1], 1);",
+                "argument to `map` must be FUNCTION, got INTEGER (line 1)",
+            ),
+            (
+                "map([1]);",
+                "wrong number of arguments. got=1, want=2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_char_at() {
+        let tests = vec![
+            ("char_at(\"héllo\", 1);", "\"é\""),
+            ("char_at(\"héllo\", 2);", "\"l\""),
+            ("char_at(\"héllo\", 5);", "null"),
+            ("char_at(\"héllo\", -1);", "null"),
+            ("char_at(\"\", 0);", "null"),
+            ("code_at(\"A\", 0);", "65"),
+            ("code_at(\"héllo\", 1);", "233"),
+            ("code_at(\"A\", 1);", "null"),
+            (
+                "char_at(1, 0);",
+                "argument to `char_at` must be STRING, got INTEGER (line 1)",
+            ),
+            (
+                "code_at(\"A\", \"0\");",
+                "argument to `code_at` must be INTEGER, got STRING (line 1)",
+            ),
+            (
+                "char_at(\"A\");",
+                "wrong number of arguments. got=1, want=2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_clone() {
+        let tests = vec![
+            ("clone(5);", "5"),
+            ("clone(\"a\");", "\"a\""),
+            ("let a = [1, [2, 3]]; clone(a) == a;", "true"),
+            (
+                "let a = [1, [2, 3]]; let b = clone(a); let b = [b[0], push(b[1], 4)]; [a, b];",
+                "[[1, [2, 3]], [1, [2, 3, 4]]]",
+            ),
+            (
+                "let h = {\"a\": [1]}; let c = set(clone(h), \"b\", 2); [h, c];",
+                "[{\"a\": [1]}, {\"a\": [1], \"b\": 2}]",
+            ),
+            ("clone(fn(x) { x + 1; })(1);", "2"),
+            (
+                "clone();",
+                "wrong number of arguments. got=0, want=1 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_keys_and_values() {
+        let tests = vec![
+            (
+                "keys({\"a\": 1, 2: true, false: [3]});",
+                "[\"a\", 2, false]",
+            ),
+            ("values({\"a\": 1, 2: true, false: [3]});", "[1, true, [3]]"),
+            (
+                "let h = {\"a\": 1, \"b\": 2}; len(keys(h)) == len(values(h));",
+                "true",
+            ),
+            ("len(keys({1: 1, 1: 2}));", "1"),
+            ("keys({});", "[]"),
+            ("values({});", "[]"),
+            (
+                "keys([1]);",
+                "argument to `keys` must be HASH, got ARRAY (line 1)",
+            ),
+            (
+                "values(1);",
+                "argument to `values` must be HASH, got INTEGER (line 1)",
+            ),
+            (
+                "keys({}, {});",
+                "wrong number of arguments. got=2, want=1 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_delete() {
+        let tests = vec![
+            (
+                "delete({\"a\": 1, \"b\": 2, \"c\": 3}, \"b\");",
+                "{\"a\": 1, \"c\": 3}",
+            ),
+            ("delete({1: true, 2: false}, 2);", "{1: true}"),
+            ("delete({\"a\": 1}, \"z\");", "{\"a\": 1}"),
+            ("delete({}, 1);", "{}"),
+            (
+                "let h = {\"a\": 1, \"b\": 2}; let g = delete(h, \"a\"); [h, g];",
+                "[{\"a\": 1, \"b\": 2}, {\"b\": 2}]",
+            ),
+            (
+                "delete({\"a\": 1}, [1]);",
+                "unusable as hash key: \"[1]\" (line 1)",
+            ),
+            (
+                "delete([1], 0);",
+                "argument to `delete` must be HASH, got ARRAY (line 1)",
+            ),
+            (
+                "delete({});",
+                "wrong number of arguments. got=1, want=2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_set() {
+        let tests = vec![
+            ("set({}, \"a\", 1)[\"a\"];", "1"),
+            ("let h = {}; set(h, \"a\", 1); h;", "{}"),
+            (
+                "set({\"a\": 1, \"b\": 2}, \"a\", 3);",
+                "{\"a\": 3, \"b\": 2}",
+            ),
+            ("set({1: true}, 2, [2]);", "{1: true, 2: [2]}"),
+            (
+                "set({}, fn(x) { x; }, 1);",
+                "unusable as hash key: \"fn(x) {\\n  x\\n}\" (line 1)",
+            ),
+            (
+                "set([], 1, 1);",
+                "argument to `set` must be HASH, got ARRAY (line 1)",
+            ),
+            (
+                "set({}, 1);",
+                "wrong number of arguments. got=2, want=3 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_add_multi() {
+        let tests = vec![
+            ("add_multi({}, \"a\", 1);", "{\"a\": [1]}"),
+            (
+                "add_multi(add_multi({}, \"a\", 1), \"a\", 2);",
+                "{\"a\": [1, 2]}",
+            ),
+            (
+                "add_multi({\"a\": [1], \"b\": [2]}, \"a\", 1);",
+                "{\"a\": [1, 1], \"b\": [2]}",
+            ),
+            (
+                "reduce([1, 2, 3, 4], {}, fn(h, x) { add_multi(h, x > 2, x); });",
+                "{false: [1, 2], true: [3, 4]}",
+            ),
+            ("let h = {}; add_multi(h, 1, 1); h;", "{}"),
+            (
+                "add_multi({\"a\": 1}, \"a\", 2);",
+                "values in `add_multi` must be ARRAY, got INTEGER at key \"a\" (line 1)",
+            ),
+            (
+                "add_multi({}, [1], 1);",
+                "unusable as hash key: \"[1]\" (line 1)",
+            ),
+            (
+                "add_multi([], 1, 1);",
+                "argument to `add_multi` must be HASH, got ARRAY (line 1)",
+            ),
+            (
+                "add_multi({}, 1);",
+                "wrong number of arguments. got=2, want=3 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_split() {
+        let tests = vec![
+            ("split(\"a,b,c\", \",\");", "[\"a\", \"b\", \"c\"]"),
+            ("len(split(\"a,b,c\", \",\"));", "3"),
+            ("split(\"abc\", \"\");", "[\"a\", \"b\", \"c\"]"),
+            ("len(split(\"héllo\", \"\"));", "5"),
+            ("split(\"a, b\", \", \");", "[\"a\", \"b\"]"),
+            ("split(\",a,\", \",\");", "[\"\", \"a\", \"\"]"),
+            ("split(\"abc\", \";\");", "[\"abc\"]"),
+            ("split(\"\", \",\");", "[\"\"]"),
+            ("split(\"\", \"\");", "[]"),
+            (
+                "split(1, \",\");",
+                "argument to `split` must be STRING, got INTEGER (line 1)",
+            ),
+            (
+                "split(\"a\", [1]);",
+                "argument to `split` must be STRING, got ARRAY (line 1)",
+            ),
+            (
+                "split(\"a\");",
+                "wrong number of arguments. got=1, want=2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_split_n() {
+        let tests = vec![
+            ("split_n(\"a,b,c,d\", \",\", 2);", "[\"a\", \"b,c,d\"]"),
+            ("split_n(\"a,b,c,d\", \",\", 1);", "[\"a,b,c,d\"]"),
+            (
+                "split_n(\"a,b,c,d\", \",\", 10);",
+                "[\"a\", \"b\", \"c\", \"d\"]",
+            ),
+            ("split_n(\"a,b,c,d\", \",\", 0);", "[]"),
+            ("split_n(\"abc\", \"\", 2);", "[\"a\", \"bc\"]"),
+            ("split_n(\"héllo\", \"\", 3);", "[\"h\", \"é\", \"llo\"]"),
+            ("split_n(\"ab\", \"\", 5);", "[\"a\", \"b\"]"),
+            ("split_n(\"\", \",\", 2);", "[\"\"]"),
+            (
+                "split_n(\"a,b\", \",\", -1);",
+                "limit of `split_n` must not be negative, got -1 (line 1)",
+            ),
+            (
+                "split_n(\"a,b\", \",\", \"2\");",
+                "argument to `split_n` must be INTEGER, got STRING (line 1)",
+            ),
+            (
+                "split_n(1, \",\", 2);",
+                "argument to `split_n` must be STRING, got INTEGER (line 1)",
+            ),
+            (
+                "split_n(\"a\", \",\");",
+                "wrong number of arguments. got=2, want=3 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_join() {
+        let tests = vec![
+            ("join([\"a\", \"b\"], \"-\");", "\"a-b\""),
+            ("join([\"a\", \"b\", \"c\"], \", \");", "\"a, b, c\""),
+            ("join([\"a\"], \"-\");", "\"a\""),
+            ("join([], \"-\");", "\"\""),
+            ("join(split(\"a,b,c\", \",\"), \";\");", "\"a;b;c\""),
+            (
+                "join([\"a\", 1], \"-\");",
+                "elements of `join` must be STRING, got INTEGER at index 1 (line 1)",
+            ),
+            (
+                "join(\"ab\", \"-\");",
+                "argument to `join` must be ARRAY, got STRING (line 1)",
+            ),
+            (
+                "join([\"a\"], 1);",
+                "argument to `join` must be STRING, got INTEGER (line 1)",
+            ),
+            (
+                "join([\"a\"]);",
+                "wrong number of arguments. got=1, want=2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_abs_min_max() {
+        let tests = vec![
+            ("abs(-5);", "5"),
+            ("abs(5);", "5"),
+            ("abs(0);", "0"),
+            (
+                "abs(-9223372036854775807 - 1);",
+                "integer overflow: abs(-9223372036854775808) (line 1)",
+            ),
+            ("min(3, 1, 2);", "1"),
+            ("max(3, 1, 2);", "3"),
+            ("min(-1, 5);", "-1"),
+            ("max(-1, -5);", "-1"),
+            (
+                "abs(\"5\");",
+                "argument to `abs` must be INTEGER, got STRING (line 1)",
+            ),
+            (
+                "min(1, true);",
+                "argument to `min` must be INTEGER, got BOOLEAN (line 1)",
+            ),
+            (
+                "max(1);",
+                "wrong number of arguments. got=1, want=2 or more (line 1)",
+            ),
+            (
+                "abs();",
+                "wrong number of arguments. got=0, want=1 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_bits() {
+        let tests = vec![
+            ("popcount(7);", "3"),
+            ("popcount(0);", "0"),
+            ("popcount(-1);", "64"),
+            ("popcount(-9223372036854775807 - 1);", "1"),
+            ("bit(5, 0);", "true"),
+            ("bit(5, 1);", "false"),
+            ("bit(0, 10);", "false"),
+            ("bit(-1, 63);", "true"),
+            ("bit(1, 63);", "false"),
+            ("set_bit(5, 1);", "7"),
+            ("set_bit(5, 0);", "5"),
+            ("set_bit(0, 63);", "-9223372036854775808"),
+            ("set_bit(-2, 0);", "-1"),
+            (
+                "bit(5, 64);",
+                "bit position of `bit` must be between 0 and 63, got 64 (line 1)",
+            ),
+            (
+                "set_bit(5, -1);",
+                "bit position of `set_bit` must be between 0 and 63, got -1 (line 1)",
+            ),
+            (
+                "popcount(\"7\");",
+                "argument to `popcount` must be INTEGER, got STRING (line 1)",
+            ),
+            (
+                "bit(true, 0);",
+                "argument to `bit` must be INTEGER, got BOOLEAN (line 1)",
+            ),
+            (
+                "set_bit(1);",
+                "wrong number of arguments. got=1, want=2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_pow() {
+        let tests = vec![
+            ("pow(2, 10);", "1024"),
+            ("pow(-3, 3);", "-27"),
+            ("pow(5, 0);", "1"),
+            ("pow(0, 0);", "1"),
+            ("pow(2, 62);", "4611686018427387904"),
+            ("pow(2, 63);", "integer overflow: pow(2, 63) (line 1)"),
+            ("pow(1, 5000000000);", "1"),
+            ("pow(0, 5000000000);", "0"),
+            ("pow(-1, 5000000000);", "1"),
+            ("pow(-1, 5000000001);", "-1"),
+            (
+                "pow(2, 5000000000);",
+                "integer overflow: pow(2, 5000000000) (line 1)",
+            ),
+            (
+                "pow(2, -1);",
+                "exponent of `pow` must not be negative, got -1 (line 1)",
+            ),
+            (
+                "pow(2, \"3\");",
+                "argument to `pow` must be INTEGER, got STRING (line 1)",
+            ),
+            (
+                "pow(true, 3);",
+                "argument to `pow` must be INTEGER, got BOOLEAN (line 1)",
+            ),
+            (
+                "pow(2);",
+                "wrong number of arguments. got=1, want=2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_format_number() {
+        let tests = vec![
+            ("format_number(0);", "\"0\""),
+            ("format_number(999);", "\"999\""),
+            ("format_number(-999);", "\"-999\""),
+            ("format_number(1000);", "\"1,000\""),
+            ("format_number(1234567);", "\"1,234,567\""),
+            ("format_number(-1234567);", "\"-1,234,567\""),
+            ("format_number(123456);", "\"123,456\""),
+            ("format_number(1234567, \".\");", "\"1.234.567\""),
+            ("format_number(1234567, \"\");", "\"1234567\""),
+            ("format_number(1234567, \" \");", "\"1 234 567\""),
+            (
+                "format_number(-9223372036854775807 - 1);",
+                "\"-9,223,372,036,854,775,808\"",
+            ),
+            (
+                "format_number(\"1000\");",
+                "argument to `format_number` must be INTEGER, got STRING (line 1)",
+            ),
+            (
+                "format_number(1000, 1);",
+                "argument to `format_number` must be STRING, got INTEGER (line 1)",
+            ),
+            (
+                "format_number();",
+                "wrong number of arguments. got=0, want=1 or 2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_range() {
+        let tests = vec![
+            ("range(1, 5);", "[1, 2, 3, 4]"),
+            ("range(0, 10, 2);", "[0, 2, 4, 6, 8]"),
+            ("range(0, 9, 3);", "[0, 3, 6]"),
+            ("range(5, 0, -1);", "[5, 4, 3, 2, 1]"),
+            ("range(-2, 2);", "[-2, -1, 0, 1]"),
+            ("range(3, 3);", "[]"),
+            ("range(3, 3, -1);", "[]"),
+            ("range(5, 0);", "[]"),
+            (
+                "range(9223372036854775806, 9223372036854775807, 5);",
+                "[9223372036854775806]",
+            ),
+            ("range(0, 5, 0);", "step for `range` must not be 0 (line 1)"),
+            (
+                "range(0, 5, -1);",
+                "step for `range` must be positive when counting up, got -1 (line 1)",
+            ),
+            (
+                "range(5, 0, 1);",
+                "step for `range` must be negative when counting down, got 1 (line 1)",
+            ),
+            (
+                "range(0, \"5\");",
+                "argument to `range` must be INTEGER, got STRING (line 1)",
+            ),
+            (
+                "range(1);",
+                "wrong number of arguments. got=1, want=2 or 3 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_shuffle_and_sample() {
+        let run = |input: &str| {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            eval_program(&program, &mut env).unwrap().inspect()
+        };
+
+        // The same seed gives the same result, and the elements are the input's.
+        let shuffled = run("seed(42); shuffle([1, 2, 3, 4, 5, 6, 7, 8]);");
+        assert_eq!(
+            shuffled,
+            run("seed(42); shuffle([1, 2, 3, 4, 5, 6, 7, 8]);")
+        );
+        assert_ne!(
+            shuffled,
+            run("seed(43); shuffle([1, 2, 3, 4, 5, 6, 7, 8]);")
+        );
+        assert_eq!(
+            run("seed(42); sort_by(shuffle([1, 2, 3, 4, 5, 6, 7, 8]), fn(x) { x; });"),
+            "[1, 2, 3, 4, 5, 6, 7, 8]"
+        );
+
+        let sampled = run("seed(7); sample([1, 2, 3, 4, 5, 6, 7, 8], 3);");
+        assert_eq!(
+            sampled,
+            run("seed(7); sample([1, 2, 3, 4, 5, 6, 7, 8], 3);")
+        );
+        let distinct = run("seed(7); let s = sample([1, 2, 3, 4, 5, 6, 7, 8], 3); \
+             [len(s), s[0] != s[1], s[1] != s[2], s[0] != s[2], all(s, fn(x) { x > 0; })];");
+        assert_eq!(distinct, "[3, true, true, true, true]");
+
+        let tests = vec![
+            ("shuffle([]);", "[]"),
+            ("let a = [1, 2, 3]; shuffle(a); a;", "[1, 2, 3]"),
+            ("sample([1, 2], 0);", "[]"),
+            ("len(sample([1, 2], 2));", "2"),
+            (
+                "sample([1, 2], 3);",
+                "cannot sample 3 elements from an array of length 2 (line 1)",
+            ),
+            (
+                "sample([1, 2], -1);",
+                "cannot sample -1 elements from an array of length 2 (line 1)",
+            ),
+            (
+                "shuffle(1);",
+                "argument to `shuffle` must be ARRAY, got INTEGER (line 1)",
+            ),
+            (
+                "seed(\"a\");",
+                "argument to `seed` must be INTEGER, got STRING (line 1)",
+            ),
+            ("seed(1);", "null"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(run(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_filter() {
+        let tests = vec![
+            ("filter([1, 2, 3, 4], fn(x) { x > 2; });", "[3, 4]"),
+            ("filter([1, 2, 3, 4], fn(x) { false; });", "[]"),
+            ("filter([], fn(x) { true; });", "[]"),
+            ("filter([1, 0, 2], fn(x) { x; });", "[1, 0, 2]"),
+            ("let a = [1, 2]; filter(a, fn(x) { x > 1; }); a;", "[1, 2]"),
+            (
+                "filter([1, true], fn(x) { x > 0; });",
+                "type mismatch: BOOLEAN > INTEGER (line 1)",
+            ),
+            (
+                "filter(1, fn(x) { x; });",
+                "argument to `filter` must be ARRAY, got INTEGER (line 1)",
+            ),
+            (
+                "filter([1], 1);",
+                "argument to `filter` must be FUNCTION, got INTEGER (line 1)",
+            ),
+            (
+                "filter([1]);",
+                "wrong number of arguments. got=1, want=2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_reduce() {
+        let tests = vec![
+            ("reduce([1, 2, 3], 0, fn(acc, x) { acc + x; });", "6"),
+            // ((0 - 1) - 2) - 3, unlike `fold_right`.
+            ("reduce([1, 2, 3], 0, fn(acc, x) { acc - x; });", "-6"),
+            (
+                "reduce([\"a\", \"b\", \"c\"], \"\", fn(acc, x) { acc + x; });",
+                "\"abc\"",
+            ),
+            ("reduce([], 7, fn(acc, x) { x; });", "7"),
+            (
+                "reduce([1, true], 0, fn(acc, x) { acc + x; });",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+            (
+                "reduce(1, 0, fn(acc, x) { x; });",
+                "argument to `reduce` must be ARRAY, got INTEGER (line 1)",
+            ),
+            (
+                "reduce([1], 0, 1);",
+                "argument to `reduce` must be FUNCTION, got INTEGER (line 1)",
+            ),
+            (
+                "reduce([1], 0);",
+                "wrong number of arguments. got=2, want=3 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_fold_right() {
+        let tests = vec![
+            // 1 - (2 - (3 - 0)); a left fold would give ((0 - 1) - 2) - 3 = -6.
+            ("fold_right([1, 2, 3], 0, fn(x, acc) { x - acc; });", "2"),
+            (
+                "fold_right([\"a\", \"b\", \"c\"], \"\", fn(x, acc) { \"(\" + x + acc + \")\"; });",
+                "\"(a(b(c)))\"",
+            ),
+            (
+                "fold_right([1, 2, 3], [], fn(x, acc) { push(acc, x); });",
+                "[3, 2, 1]",
+            ),
+            ("fold_right([], 7, fn(x, acc) { x; });", "7"),
+            (
+                "fold_right([1, true], 0, fn(x, acc) { x + acc; });",
+                "type mismatch: BOOLEAN + INTEGER (line 1)",
+            ),
+            (
+                "fold_right(1, 0, fn(x, acc) { x; });",
+                "argument to `fold_right` must be ARRAY, got INTEGER (line 1)",
+            ),
+            (
+                "fold_right([1], 0, 1);",
+                "argument to `fold_right` must be FUNCTION, got INTEGER (line 1)",
+            ),
+            (
+                "fold_right([1], 0);",
+                "wrong number of arguments. got=2, want=3 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_rotate() {
+        let tests = vec![
+            ("rotate([1, 2, 3, 4], 1);", "[2, 3, 4, 1]"),
+            ("rotate([1, 2, 3, 4], -1);", "[4, 1, 2, 3]"),
+            ("rotate([1, 2, 3, 4], 0);", "[1, 2, 3, 4]"),
+            ("rotate([1, 2, 3, 4], 6);", "[3, 4, 1, 2]"),
+            ("rotate([1, 2, 3, 4], -9);", "[4, 1, 2, 3]"),
+            ("rotate([], 3);", "[]"),
+            ("let a = [1, 2, 3]; rotate(a, 1); a;", "[1, 2, 3]"),
+            (
+                "rotate(1, 1);",
+                "argument to `rotate` must be ARRAY, got INTEGER (line 1)",
+            ),
+            (
+                "rotate([1], true);",
+                "argument to `rotate` must be INTEGER, got BOOLEAN (line 1)",
+            ),
+            (
+                "rotate([1]);",
+                "wrong number of arguments. got=1, want=2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_zip() {
+        let tests = vec![
+            ("zip([1, 2], [\"a\", \"b\"]);", "[[1, \"a\"], [2, \"b\"]]"),
+            (
+                "zip([1, 2, 3], [\"a\", \"b\", \"c\"], [true, false, true]);",
+                "[[1, \"a\", true], [2, \"b\", false], [3, \"c\", true]]",
+            ),
+            (
+                "zip([1, 2, 3], [\"a\"], [true, false]);",
+                "[[1, \"a\", true]]",
+            ),
+            ("zip([1, 2], [], [3]);", "[]"),
+            ("zip([1, 2]);", "[[1], [2]]"),
+            (
+                "zip([1], 2);",
+                "argument to `zip` must be ARRAY, got INTEGER (line 1)",
+            ),
+            (
+                "zip();",
+                "wrong number of arguments. got=0, want=at least 1 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_intersperse() {
+        let tests = vec![
+            ("intersperse([1, 2, 3], 0);", "[1, 0, 2, 0, 3]"),
+            ("intersperse([1], 0);", "[1]"),
+            ("intersperse([], 0);", "[]"),
+            (
+                "intersperse([\"a\", \"b\"], [\",\"]);",
+                "[\"a\", [\",\"], \"b\"]",
+            ),
+            (
+                "intersperse(1, 0);",
+                "argument to `intersperse` must be ARRAY, got INTEGER (line 1)",
+            ),
+            (
+                "intersperse([1]);",
+                "wrong number of arguments. got=1, want=2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_count_by() {
+        let tests = vec![
+            (
+                "count_by([\"a\", \"bb\", \"cc\", \"d\", \"eee\"], fn(w) { len(w); });",
+                "{1: 2, 2: 2, 3: 1}",
+            ),
+            (
+                "count_by([1, 2, 3, 4, 5], fn(n) { if (n > 2) { \"big\"; } else { \"small\"; }; });",
+                "{\"small\": 2, \"big\": 3}",
+            ),
+            ("count_by([1, 1, 2], identity);", "{1: 2, 2: 1}"),
+            ("count_by([], fn(x) { x; });", "{}"),
+            (
+                "count_by([1], fn(x) { [x]; });",
+                "unusable as hash key: \"[1]\" (line 1)",
+            ),
+            (
+                "count_by([1], fn(x) { x + true; });",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+            (
+                "count_by({}, len);",
+                "argument to `count_by` must be ARRAY, got HASH (line 1)",
+            ),
+            (
+                "count_by([1], 1);",
+                "argument to `count_by` must be FUNCTION, got INTEGER (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new_with_prelude();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_filter_hash() {
+        let tests = vec![
+            (
+                "filter_hash({\"a\": 1, \"b\": 2, \"c\": 3}, fn(k, v) { v > 1; });",
+                "{\"b\": 2, \"c\": 3}",
+            ),
+            (
+                "filter_hash({\"c\": 3, \"a\": 1, \"b\": 2}, fn(k, v) { k != \"a\"; });",
+                "{\"c\": 3, \"b\": 2}",
+            ),
+            ("filter_hash({\"a\": 1}, fn(k, v) { false; });", "{}"),
+            ("filter_hash({}, fn(k, v) { true; });", "{}"),
+            (
+                "let h = {\"a\": 1}; filter_hash(h, fn(k, v) { false; }); h;",
+                "{\"a\": 1}",
+            ),
+            (
+                "filter_hash({\"a\": 1}, fn(k, v) { v + true; });",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+            (
+                "filter_hash([1], fn(k, v) { true; });",
+                "argument to `filter_hash` must be HASH, got ARRAY (line 1)",
+            ),
+            (
+                "filter_hash({}, 1);",
+                "argument to `filter_hash` must be FUNCTION, got INTEGER (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_table() {
+        let tests = vec![
+            (
+                "table([[\"a\", \"bbb\"], [\"cc\", \"d\"]]);",
+                "a   bbb\ncc  d",
+            ),
+            (
+                "table([[\"name\", \"qty\"], [\"apple\", \"3\"], [\"fig\"]]);",
+                "name   qty\napple  3\nfig",
+            ),
+            ("table([[\"é\", \"x\"], [\"ab\", \"y\"]]);", "é   x\nab  y"),
+            ("table([]);", ""),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            if let Some(string) = downcast_ref!(&results, StringObj) {
+                assert_eq!(string.value, expected);
+            } else {
+                panic!("Expected String object");
+            }
+        }
+
+        let tests = vec![
+            (
+                "table([[\"a\", 1]]);",
+                "argument to `table` must be STRING, got INTEGER (line 1)",
+            ),
+            (
+                "table([\"a\"]);",
+                "table rows must be ARRAY, got STRING (line 1)",
+            ),
+            (
+                "table(1);",
+                "argument to `table` must be ARRAY, got INTEGER (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected);
+        }
+    }
+
+    #[test]
+    fn test_builtin_to_json_string() {
+        let tests = vec![
+            ("to_json_string(1);", "1"),
+            ("to_json_string(\"a\");", "\"a\""),
+            (
+                "to_json_string({\"b\": [1, true, \"x\"], \"a\": {1: false}, \"c\": first([])});",
+                "{\"b\":[1,true,\"x\"],\"a\":{\"1\":false},\"c\":null}",
+            ),
+            (
+                "to_json_string({\"b\": [1, true], \"a\": {}, \"c\": []}, true);",
+                "{\n  \"b\": [\n    1,\n    true\n  ],\n  \"a\": {},\n  \"c\": []\n}",
+            ),
+            ("to_json_string([1, [2]], false);", "[1,[2]]"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            if let Some(string) = downcast_ref!(&results, StringObj) {
+                assert_eq!(string.value, expected, "{}", input);
+            } else {
+                panic!("Expected String object, got {}", results.inspect());
+            }
+        }
+
+        let tests = vec![
+            (
+                "to_json_string([fn(x) { x; }]);",
+                "cannot serialize FUNCTION to JSON (line 1)",
+            ),
+            (
+                "to_json_string({\"f\": len});",
+                "cannot serialize BUILTIN to JSON (line 1)",
+            ),
+            (
+                "to_json_string(1, 1);",
+                "argument to `to_json_string` must be BOOLEAN, got INTEGER (line 1)",
+            ),
+            (
+                "to_json_string();",
+                "wrong number of arguments. got=0, want=1 or 2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected);
+        }
+    }
+
+    #[cfg(feature = "io")]
+    #[test]
+    fn test_builtin_puts() {
+        assert!(get_builtin("puts").is_some());
+
+        let tests = vec!["puts(1, \"two\", true);", "puts();", "puts([1, 2]);"];
+
+        for input in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert!(downcast_ref!(&results, Null).is_some());
+        }
+    }
+
+    #[cfg(not(feature = "io"))]
+    #[test]
+    fn test_builtin_puts_needs_io() {
+        assert!(get_builtin("puts").is_none());
+
+        let program = parse_program("puts(1);").unwrap();
+        let mut env = Environment::new();
+        let results = eval_program(&program, &mut env).unwrap();
+        assert_eq!(results.inspect(), "identifier not found: puts (line 1)");
+    }
+
+    #[test]
+    fn test_builtin_functions_with_errors() {
+        let tests = vec![
+            (
+                "len(1);",
+                "argument to `len` not supported, got INTEGER (line 1)",
+            ),
+            (
+                "len(\"one\", \"two\");",
+                "wrong number of arguments. got=2, want=1 (line 1)",
+            ),
+            (
+                "first(1);",
+                "argument to `first` must be ARRAY, got INTEGER (line 1)",
+            ),
+            (
+                "last(1);",
+                "argument to `last` must be ARRAY, got INTEGER (line 1)",
+            ),
+            (
+                "rest(1);",
+                "argument to `rest` must be ARRAY, got INTEGER (line 1)",
+            ),
+            (
+                "push(1, 1);",
+                "argument to `push` must be ARRAY, got INTEGER (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env);
+            match results {
+                Ok(result) => {
+                    if downcast_ref!(result, Error).is_some() {
+                        assert_eq!(result.inspect(), expected);
+                    } else {
+                        panic!("Expected error object");
+                    }
+                }
+                Err(e) => panic!("Error: {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_builtin_contains() {
+        let tests = vec![
+            ("contains([1, 2, 3], 2);", "true"),
+            ("contains([1, 2, 3], 4);", "false"),
+            ("contains([1, 2, 3], \"2\");", "false"),
+            ("contains([[1], {\"a\": 1}], {\"a\": 1});", "true"),
+            ("contains(\"hello\", \"ell\");", "true"),
+            ("contains(\"hello\", \"Ell\");", "false"),
+            ("contains(\"hello\", \"\");", "true"),
+            ("contains({\"a\": 1}, \"a\");", "true"),
+            ("contains({\"a\": 1}, \"b\");", "false"),
+            ("contains({1: \"a\"}, \"a\");", "false"),
+            ("contains({1: \"a\"}, true);", "false"),
+            (
+                "contains(\"hello\", 1);",
+                "argument to `contains` must be STRING, got INTEGER (line 1)",
+            ),
+            (
+                "contains({}, [1]);",
+                "unusable as hash key: \"[1]\" (line 1)",
+            ),
+            (
+                "contains(1, 1);",
+                "argument to `contains` must be ARRAY, STRING or HASH, got INTEGER (line 1)",
+            ),
+            (
+                "contains([1]);",
+                "wrong number of arguments. got=1, want=2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_apply_function_from_builtin() {
+        fn twice(args: Vec<ObjectRef>) -> ObjectRef {
+            let once = apply_function(args[0].clone(), &args[1..]);
+            if is_error(&once) {
+                return once;
+            }
+            apply_function(args[0].clone(), &[once])
+        }
+
+        let tests = vec![
+            ("twice(fn(x) { x * 2; }, 3);", "12"),
+            ("let n = 10; twice(fn(x) { x + n; }, 1);", "21"),
+            (
+                "twice(len, \"abc\");",
+                "argument to `len` not supported, got INTEGER (line 1)",
+            ),
+            (
+                "twice(fn(x) { x + true; }, 1);",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            env.set(
+                "twice".to_string(),
+                box_it!(Builtin {
+                    name: "twice",
+                    func: twice,
+                    takes_errors: false,
+                }),
+            );
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_group_consecutive() {
+        let tests = vec![
+            (
+                "group_consecutive([1, 1, 2, 3, 3, 3]);",
+                "[[1, 1], [2], [3, 3, 3]]",
+            ),
+            ("group_consecutive([7, 7, 7]);", "[[7, 7, 7]]"),
+            ("group_consecutive([1, 2, 3]);", "[[1], [2], [3]]"),
+            ("group_consecutive([1, 2, 1]);", "[[1], [2], [1]]"),
+            ("group_consecutive([]);", "[]"),
+            (
+                "group_consecutive([\"a\", \"a\", 1, \"1\", [1], [1]]);",
+                "[[\"a\", \"a\"], [1], [\"1\"], [[1], [1]]]",
+            ),
+            (
+                "group_consecutive(\"aab\");",
+                "argument to `group_consecutive` must be ARRAY, got STRING (line 1)",
+            ),
+            (
+                "group_consecutive();",
+                "wrong number of arguments. got=0, want=1 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_transpose() {
+        let tests = vec![
+            (
+                "transpose([[1, 2, 3], [4, 5, 6]]);",
+                "[[1, 4], [2, 5], [3, 6]]",
+            ),
+            ("transpose([[1, 2, 3]]);", "[[1], [2], [3]]"),
+            ("transpose([[1], [2], [3]]);", "[[1, 2, 3]]"),
+            (
+                "transpose(transpose([[1, 2], [3, 4]]));",
+                "[[1, 2], [3, 4]]",
+            ),
+            ("transpose([]);", "[]"),
+            ("transpose([[], []]);", "[]"),
+            (
+                "transpose([[1, 2], [3]]);",
+                "rows of `transpose` must all have length 2, got 1 at index 1 (line 1)",
+            ),
+            (
+                "transpose([[1], 2]);",
+                "rows of `transpose` must be ARRAY, got INTEGER at index 1 (line 1)",
+            ),
+            (
+                "transpose(1);",
+                "argument to `transpose` must be ARRAY, got INTEGER (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_rle_rld() {
+        let tests = vec![
+            ("rle([1, 1, 2, 3, 3, 3]);", "[[1, 2], [2, 1], [3, 3]]"),
+            ("rle([]);", "[]"),
+            ("rle([[1], [1], \"a\"]);", "[[[1], 2], [\"a\", 1]]"),
+            ("rld([[1, 2], [2, 1], [3, 3]]);", "[1, 1, 2, 3, 3, 3]"),
+            ("rld([[true, 0], [false, 2]]);", "[false, false]"),
+            ("rld([]);", "[]"),
+            (
+                "rld([[1, 2], [2]]);",
+                "elements of `rld` must be [value, count] pairs, got [2] at index 1 (line 1)",
+            ),
+            (
+                "rld([[1, \"2\"]]);",
+                "elements of `rld` must be [value, count] pairs, got [1, \"2\"] at index 0 (line 1)",
+            ),
+            (
+                "rld([1]);",
+                "elements of `rld` must be [value, count] pairs, got 1 at index 0 (line 1)",
+            ),
+            (
+                "rld([[1, -1]]);",
+                "count in `rld` must not be negative, got -1 at index 0 (line 1)",
+            ),
+            (
+                "rle(\"aab\");",
+                "argument to `rle` must be ARRAY, got STRING (line 1)",
+            ),
+            (
+                "rld([[1, 9223372036854775807]]);",
+                "count in `rld` is too large, got 9223372036854775807 at index 0 (line 1)",
+            ),
+            (
+                "rld([[1, 16777216], [2, 1]]);",
+                "count in `rld` is too large, got 1 at index 1 (line 1)",
+            ),
+            ("rld();", "wrong number of arguments. got=0, want=1 (line 1)"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+
+        let round_trips = vec![
+            "[1, 1, 2, 3, 3, 3]",
+            "[]",
+            "[\"a\", \"a\", \"b\", \"a\"]",
+            "[[1, 2], [1, 2], {1: 2}, true, true]",
+        ];
+
+        for input in round_trips {
+            let program = parse_program(&format!("rld(rle({}));", input)).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_slice() {
+        let tests = vec![
+            ("slice([1, 2, 3, 4], 1, 3);", "[2, 3]"),
+            ("slice(\"hello\", 1, 3);", "\"el\""),
+            ("slice([1, 2, 3, 4], 0, 4);", "[1, 2, 3, 4]"),
+            ("slice([1, 2, 3, 4], -2, 4);", "[3, 4]"),
+            ("slice(\"hello\", 1, -1);", "\"ell\""),
+            ("slice(\"héllo\", 0, 2);", "\"hé\""),
+            ("slice([1, 2, 3], 1, 10);", "[2, 3]"),
+            ("slice([1, 2, 3], -10, 2);", "[1, 2]"),
+            ("slice([1, 2, 3], 2, 1);", "[]"),
+            ("slice(\"abc\", 5, 9);", "\"\""),
+            ("slice([], 0, 1);", "[]"),
+            (
+                "slice({}, 0, 1);",
+                "argument to `slice` must be ARRAY or STRING, got HASH (line 1)",
+            ),
+            (
+                "slice([1], \"0\", 1);",
+                "argument to `slice` must be INTEGER, got STRING (line 1)",
+            ),
+            (
+                "slice([1], 0);",
+                "wrong number of arguments. got=2, want=3 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_take_drop() {
+        let tests = vec![
+            ("take([1, 2, 3], 2);", "[1, 2]"),
+            ("drop([1, 2, 3], 2);", "[3]"),
+            ("take([1, 2, 3], 0);", "[]"),
+            ("drop([1, 2, 3], 0);", "[1, 2, 3]"),
+            ("take([1, 2, 3], 5);", "[1, 2, 3]"),
+            ("drop([1, 2, 3], 5);", "[]"),
+            ("take([], 1);", "[]"),
+            (
+                "let a = [1, 2, 3]; [take(a, 1), drop(a, 1), a];",
+                "[[1], [2, 3], [1, 2, 3]]",
+            ),
+            (
+                "take([1, 2, 3], -1);",
+                "count of `take` must not be negative, got -1 (line 1)",
+            ),
+            (
+                "drop([1, 2, 3], -2);",
+                "count of `drop` must not be negative, got -2 (line 1)",
+            ),
+            (
+                "take(\"abc\", 1);",
+                "argument to `take` must be ARRAY, got STRING (line 1)",
+            ),
+            (
+                "drop([1], \"1\");",
+                "argument to `drop` must be INTEGER, got STRING (line 1)",
+            ),
+            (
+                "take([1]);",
+                "wrong number of arguments. got=1, want=2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_reverse() {
+        let tests = vec![
+            ("reverse([1, 2, 3]);", "[3, 2, 1]"),
+            ("reverse([]);", "[]"),
+            ("reverse([[1, 2], \"a\"]);", "[\"a\", [1, 2]]"),
+            ("let a = [1, 2]; reverse(a); a;", "[1, 2]"),
+            ("reverse(\"abc\");", "\"cba\""),
+            ("reverse(\"héllo\");", "\"olléh\""),
+            ("reverse(\"\");", "\"\""),
+            (
+                "reverse(1);",
+                "argument to `reverse` must be ARRAY or STRING, got INTEGER (line 1)",
+            ),
+            (
+                "reverse([1], [2]);",
+                "wrong number of arguments. got=2, want=1 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_builtin_sort() {
+        let tests = vec![
+            ("sort([3, 1, 2]);", "[1, 2, 3]"),
+            ("sort([10, -1, 2, 2]);", "[-1, 2, 2, 10]"),
+            (
+                "sort([\"pear\", \"apple\", \"Zoo\"]);",
+                "[\"Zoo\", \"apple\", \"pear\"]",
+            ),
+            ("sort([]);", "[]"),
+            ("let a = [3, 1, 2]; sort(a); a;", "[3, 1, 2]"),
+            ("sort([3, 1, 2], fn(a, b) { b - a; });", "[3, 2, 1]"),
+            (
+                "sort([[2, \"b\"], [1, \"x\"], [2, \"a\"]], fn(a, b) { a[0] - b[0]; });",
+                "[[1, \"x\"], [2, \"b\"], [2, \"a\"]]",
+            ),
+            (
+                "sort([1, \"a\", true], fn(a, b) { 0; });",
+                "[1, \"a\", true]",
+            ),
+            ("sort([1, 2, 3, 4], fn(a, b) { 1; });", "[1, 2, 3, 4]"),
+            (
+                "sort([1, \"a\"]);",
+                "cannot sort array of mixed types (line 1)",
+            ),
+            (
+                "sort([true, false]);",
+                "cannot sort array of BOOLEAN (line 1)",
+            ),
+            (
+                "sort([2, 1], fn(a, b) { true; });",
+                "comparator for `sort` must return INTEGER, got BOOLEAN (line 1)",
+            ),
+            (
+                "sort([2, true], fn(a, b) { a - b; });",
+                "type mismatch: BOOLEAN - INTEGER (line 1)",
+            ),
+            (
+                "sort([1], 1);",
+                "argument to `sort` must be FUNCTION, got INTEGER (line 1)",
+            ),
+            (
+                "sort(1);",
+                "argument to `sort` must be ARRAY, got INTEGER (line 1)",
+            ),
+            (
+                "sort();",
+                "wrong number of arguments. got=0, want=1 or 2 (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
         }
     }
 
     #[test]
-    fn test_builtin_functions_with_integer() {
+    fn test_builtin_sort_by() {
         let tests = vec![
-            ("len(\"\");", 0),
-            ("len(\"four\");", 4),
-            ("len(\"hello world\");", 11),
-            ("len([1,2,3]);", 3),
-            ("len([]);", 0),
-            ("len([1, 2 * 2, 3 + 3]);", 3),
-            ("first([1, 2, 3]);", 1),
-            ("last([1, 2, 3]);", 3),
+            ("sort_by([3, 1, 2], fn(x) { x; });", "[1, 2, 3]"),
+            ("sort_by([3, 1, 2], fn(x) { -x; });", "[3, 2, 1]"),
+            (
+                "sort_by([\"bb\", \"a\", \"ccc\"], fn(x) { len(x); });",
+                "[\"a\", \"bb\", \"ccc\"]",
+            ),
+            (
+                "sort_by([[2, \"b\"], [1, \"a\"]], fn(x) { x[1]; });",
+                "[[1, \"a\"], [2, \"b\"]]",
+            ),
+            ("sort_by([], fn(x) { x; });", "[]"),
+            (
+                "sort_by([3, 1], fn(x) { x + true; });",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+            (
+                "sort_by([1, 2], fn(x) { if (x > 1) { \"a\"; } else { 1; }; });",
+                "sort keys must be all INTEGER or all STRING (line 1)",
+            ),
+            (
+                "sort_by(1, fn(x) { x; });",
+                "argument to `sort_by` must be ARRAY, got INTEGER (line 1)",
+            ),
+            (
+                "sort_by([1], 1);",
+                "argument to `sort_by` must be FUNCTION, got INTEGER (line 1)",
+            ),
         ];
 
         for (input, expected) in tests {
             let program = parse_program(input).unwrap();
             let mut env = Environment::new();
-            let results = eval_program(&program, &mut env);
-            match results {
-                Ok(result) => {
-                    if let Some(integer) = downcast_ref!(&result, Integer) {
-                        assert_eq!(integer.value, expected);
-                    } else {
-                        panic!("Expected Integer object");
-                    }
-                }
-                Err(e) => panic!("Error: {}", e),
-            }
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected);
         }
     }
 
     #[test]
-    fn test_builtin_functions_with_slices() {
-        let tests: Vec<(&str, &[i64])> = vec![
-            ("rest([1, 2, 3]);", &[2, 3]),
-            ("push([], 1);", &[1]),
-            ("push([1], 2);", &[1, 2]),
-            ("push([1, 2], 3);", &[1, 2, 3]),
+    fn test_builtin_sort_by_is_stable() {
+        let input = "
+        let records = [[3, \"a\"], [1, \"b\"], [3, \"c\"], [2, \"d\"], [1, \"e\"]];
+        sort_by(records, fn(r) { r[0]; });
+        ";
+        let program = parse_program(input).unwrap();
+        let mut env = Environment::new();
+        let results = eval_program(&program, &mut env).unwrap();
+        assert_eq!(
+            results.inspect(),
+            "[[1, \"b\"], [1, \"e\"], [2, \"d\"], [3, \"a\"], [3, \"c\"]]"
+        );
+    }
+
+    #[test]
+    fn test_builtin_to_upper_to_lower() {
+        let tests = vec![
+            ("to_upper(\"abc\");", "\"ABC\""),
+            ("to_lower(\"XYZ\");", "\"xyz\""),
+            ("to_upper(\"a1-b\");", "\"A1-B\""),
+            ("to_upper(\"straße\");", "\"STRASSE\""),
+            ("to_lower(\"ÄÖÜ\");", "\"äöü\""),
+            ("to_lower(\"\");", "\"\""),
+            (
+                "to_upper(1);",
+                "argument to `to_upper` must be STRING, got INTEGER (line 1)",
+            ),
+            (
+                "to_lower([]);",
+                "argument to `to_lower` must be STRING, got ARRAY (line 1)",
+            ),
+            (
+                "to_upper(\"a\", \"b\");",
+                "wrong number of arguments. got=2, want=1 (line 1)",
+            ),
         ];
 
         for (input, expected) in tests {
             let program = parse_program(input).unwrap();
             let mut env = Environment::new();
-            let results = eval_program(&program, &mut env);
-            match results {
-                Ok(result) => {
-                    if let Some(array) = downcast_ref!(&result, Array) {
-                        for (i, element) in array.elements.iter().enumerate() {
-                            if let Some(integer) = downcast_ref!(element, Integer) {
-                                assert_eq!(integer.value, expected[i]);
-                            } else {
-                                panic!("Expected Integer object");
-                            }
-                        }
-                    } else {
-                        panic!("Expected Array object");
-                    }
-                }
-                Err(e) => panic!("Error: {}", e),
-            }
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
         }
     }
 
     #[test]
-    fn test_builtin_functions_with_null() {
-        let tests = vec![("first([]);", "null"), ("last([]);", "null")];
+    fn test_builtin_trim() {
+        let tests = vec![
+            ("trim(\"  hi  \");", "\"hi\""),
+            ("trim(\"\\t hi there\\n\");", "\"hi there\""),
+            ("trim(\"hi\");", "\"hi\""),
+            ("trim(\"   \");", "\"\""),
+            ("trim(\"--hi-+\", \"-+\");", "\"hi\""),
+            ("trim(\" hi \", \"\");", "\" hi \""),
+            (
+                "trim(1);",
+                "argument to `trim` must be STRING, got INTEGER (line 1)",
+            ),
+            (
+                "trim(\"a\", 1);",
+                "argument to `trim` must be STRING, got INTEGER (line 1)",
+            ),
+            (
+                "trim();",
+                "wrong number of arguments. got=0, want=1 or 2 (line 1)",
+            ),
+        ];
 
         for (input, expected) in tests {
             let program = parse_program(input).unwrap();
             let mut env = Environment::new();
-            let results = eval_program(&program, &mut env);
-            match results {
-                Ok(result) => {
-                    if let Some(null) = downcast_ref!(&result, Null) {
-                        assert_eq!(null.inspect(), expected);
-                    } else {
-                        panic!("Expected Null object");
-                    }
-                }
-                Err(e) => panic!("Error: {}", e),
-            }
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
         }
     }
 
     #[test]
-    fn test_builtin_functions_with_errors() {
+    fn test_builtin_ignore_case() {
         let tests = vec![
-            ("len(1);", "argument to `len` not supported, got INTEGER"),
+            ("eq_ignore_case(\"ABC\", \"abc\");", "true"),
+            ("eq_ignore_case(\"abc\", \"abd\");", "false"),
+            ("eq_ignore_case(\"ÄÖÜ\", \"äöü\");", "true"),
+            ("eq_ignore_case(\"straße\", \"STRASSE\");", "true"),
+            ("eq_ignore_case(\"ΣΊΣΥΦΟΣ\", \"σίσυφοσ\");", "true"),
+            ("contains_ignore_case(\"Hello\", \"ell\");", "true"),
+            ("contains_ignore_case(\"Hello\", \"ELL\");", "true"),
+            ("contains_ignore_case(\"GRÜN\", \"rü\");", "true"),
+            ("contains_ignore_case(\"Hello\", \"xyz\");", "false"),
+            ("contains_ignore_case(\"Hello\", \"\");", "true"),
             (
-                "len(\"one\", \"two\");",
-                "wrong number of arguments. got=2, want=1",
+                "eq_ignore_case(\"a\", 1);",
+                "argument to `eq_ignore_case` must be STRING, got INTEGER (line 1)",
             ),
             (
-                "first(1);",
-                "argument to `first` must be ARRAY, got INTEGER",
+                "contains_ignore_case(true, \"a\");",
+                "argument to `contains_ignore_case` must be STRING, got BOOLEAN (line 1)",
             ),
-            ("last(1);", "argument to `last` must be ARRAY, got INTEGER"),
-            ("rest(1);", "argument to `rest` must be ARRAY, got INTEGER"),
             (
-                "push(1, 1);",
-                "argument to `push` must be ARRAY, got INTEGER",
+                "eq_ignore_case(\"a\");",
+                "wrong number of arguments. got=1, want=2 (line 1)",
             ),
         ];
 
         for (input, expected) in tests {
             let program = parse_program(input).unwrap();
             let mut env = Environment::new();
-            let results = eval_program(&program, &mut env);
-            match results {
-                Ok(result) => {
-                    if let Some(_) = downcast_ref!(result, Error) {
-                        assert_eq!(result.inspect(), expected);
-                    } else {
-                        panic!("Expected error object");
-                    }
-                }
-                Err(e) => panic!("Error: {}", e),
-            }
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
         }
     }
 
@@ -895,6 +3958,7 @@ mod tests {
                 "let myArray = [1, 2, 3]; let i = myArray[0]; myArray[i];",
                 2,
             ),
+            ("[[1, 2], [3, 4]][1][0];", 3),
         ];
 
         for (input, expected) in tests {
@@ -907,14 +3971,158 @@ mod tests {
 
     #[test]
     fn test_array_index_null_object() {
-        let input = "[1, 2, 3][3];";
+        let tests = vec!["[1, 2, 3][3];", "[][0];", "[][-1];", "[1, 2, 3][-4];"];
+
+        for input in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            if let Some(null) = downcast_ref!(&results, Null) {
+                assert_eq!(null.inspect(), "null");
+            } else {
+                panic!("Expected Null object");
+            }
+        }
+    }
+
+    #[test]
+    fn test_max_call_depth() {
+        let tests = vec![
+            (
+                "let f = fn(x) { f(x); }; f(1);",
+                "maximum recursion depth exceeded (line 1)",
+            ),
+            (
+                "let f = fn(n) { if (n == 0) { 0; } else { 1 + f(n - 1); }; }; f(900);",
+                "900",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+
+        // The depth unwinds with the error, so later calls aren't affected.
+        let mut env = Environment::new();
+        let program = parse_program("let f = fn(x) { f(x); }; f(1);").unwrap();
+        let results = eval_program(&program, &mut env).unwrap();
+        assert!(is_error(&results));
+        let program =
+            parse_program("let f = fn(n) { if (n == 0) { 0; } else { 1 + f(n - 1); }; }; f(999);")
+                .unwrap();
+        let results = eval_program(&program, &mut env).unwrap();
+        assert_eq!(results.inspect(), "999");
+
+        let config = EvalConfig {
+            max_call_depth: 10,
+            ..EvalConfig::default()
+        };
+        let tests = vec![
+            (
+                "let f = fn(n) { if (n == 0) { 0; } else { 1 + f(n - 1); }; }; f(9);",
+                "9",
+            ),
+            (
+                "let f = fn(n) { if (n == 0) { 0; } else { 1 + f(n - 1); }; }; f(10);",
+                "maximum recursion depth exceeded (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::with_config(config);
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        let tests = vec![
+            (
+                "let count = fn(n) { if (n == 0) { return 0; }; return count(n - 1); }; count(1000000);",
+                "0",
+            ),
+            (
+                "let sum = fn(n, acc) { if (n == 0) { return acc; }; return sum(n - 1, acc + n); }; sum(100000, 0);",
+                "5000050000",
+            ),
+            (
+                "let even = fn(n) { if (n == 0) { return true; }; return odd(n - 1); }; let odd = fn(n) { if (n == 0) { return false; }; return even(n - 1); }; even(10001);",
+                "false",
+            ),
+            ("let f = fn(a) { return len(a); }; f(\"abc\");", "3"),
+            (
+                "let f = fn(n) { return n(1); }; f(5);",
+                "not a function: \"INTEGER\" (line 1)",
+            ),
+            (
+                "let f = fn(n) { if (n == 0) { return n + true; }; return f(n - 1); }; f(5000);",
+                "type mismatch: INTEGER + BOOLEAN (line 1)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_index_out_of_range_config() {
+        let input = "[1, 2, 3][5];";
         let program = parse_program(input).unwrap();
+
         let mut env = Environment::new();
         let results = eval_program(&program, &mut env).unwrap();
-        if let Some(null) = downcast_ref!(&results, Null) {
-            assert_eq!(null.inspect(), "null");
-        } else {
-            panic!("Expected Null object");
+        assert_eq!(results.inspect(), "null");
+
+        let config = EvalConfig {
+            index_out_of_range: IndexOutOfRange::Error,
+            ..EvalConfig::default()
+        };
+        let tests = vec![
+            ("[1, 2, 3][5];", "index out of range: 5 (length 3) (line 1)"),
+            (
+                "[1, 2, 3][-4];",
+                "index out of range: -4 (length 3) (line 1)",
+            ),
+            ("[][0];", "index out of range: 0 (length 0) (line 1)"),
+            (
+                "let f = fn(a) { a[3]; }; f([1]);",
+                "index out of range: 3 (length 1) (line 1)",
+            ),
+            ("[1, 2, 3][-1];", "3"),
+            ("{1: 2}[3];", "null"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::with_config(config);
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_array_negative_index_expressions() {
+        let tests = vec![
+            ("[1, 2, 3][-1];", 3),
+            ("[1, 2, 3][-2];", 2),
+            ("[1, 2, 3][-3];", 1),
+            ("let a = [1, 2, 3]; a[len(a) - 1] + a[-1];", 6),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_is_integer(&results, expected);
         }
     }
 
@@ -985,6 +4193,110 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_hash_inspect() {
+        let tests = vec![
+            ("{};", "{}"),
+            ("{\"a\": 1};", "{\"a\": 1}"),
+            ("{1: [true, \"x\"]};", "{1: [true, \"x\"]}"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected);
+        }
+
+        // Pairs are shown in insertion order.
+        let program = parse_program("{\"two\": 2, \"one\": 1, \"three\": 3};").unwrap();
+        let mut env = Environment::new();
+        let inspected = eval_program(&program, &mut env).unwrap().inspect();
+        assert_eq!(inspected, "{\"two\": 2, \"one\": 1, \"three\": 3}");
+    }
+
+    #[test]
+    fn test_array_equality() {
+        let tests = vec![
+            ("[1, 2] == [1, 2];", "true"),
+            ("[1, 2] != [1, 2];", "false"),
+            ("[1, 2] == [2, 1];", "false"),
+            ("[1, 2] == [1, 2, 3];", "false"),
+            ("[] == [];", "true"),
+            (
+                "[1, [2, [\"x\", true]]] == [1, [2, [\"x\", true]]];",
+                "true",
+            ),
+            (
+                "[1, [2, [\"x\", true]]] == [1, [2, [\"x\", false]]];",
+                "false",
+            ),
+            ("[1] == [\"1\"];", "false"),
+            ("[{\"a\": [1]}] == [{\"a\": [1]}];", "true"),
+            ("{\"a\": [1, 2]} == {\"a\": [1, 2]};", "true"),
+            ("{\"a\": [1, 2]} != {\"a\": [2, 1]};", "true"),
+            ("[first([])] == [first([])];", "true"),
+            ("first([]) == last([]);", "true"),
+            ("first([]) != last([]);", "false"),
+            ("[1] == 1;", "type mismatch: ARRAY == INTEGER (line 1)"),
+            ("[1] < [2];", "unknown operator: ARRAY < ARRAY (line 1)"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_hash_equality() {
+        let tests = vec![
+            (
+                "let a = {\"a\": 1, \"b\": 2}; let b = {\"b\": 2, \"a\": 1}; a == b;",
+                true,
+            ),
+            (
+                "let a = {\"a\": 1, \"b\": 2}; let b = {\"b\": 2, \"a\": 1}; a != b;",
+                false,
+            ),
+            ("let a = {}; let b = {}; a == b;", true),
+            (
+                "let a = {1: true, 2: \"x\"}; let b = {2: \"x\", 1: true}; a == b;",
+                true,
+            ),
+            (
+                "let a = {\"a\": 1, \"b\": 2}; let b = {\"a\": 1, \"b\": 3}; a == b;",
+                false,
+            ),
+            (
+                "let a = {\"a\": 1, \"b\": 2}; let b = {\"a\": 1, \"b\": 3}; a != b;",
+                true,
+            ),
+            (
+                "let a = {\"a\": 1, \"b\": 2}; let b = {\"a\": 1, \"c\": 2}; a == b;",
+                false,
+            ),
+            (
+                "let a = {\"a\": 1}; let b = {\"a\": 1, \"b\": 2}; a == b;",
+                false,
+            ),
+            ("let a = {1: 1}; let b = {true: 1}; a == b;", false),
+            (
+                "let a = {\"a\": {\"b\": 1}}; let b = {\"a\": {\"b\": 1}}; a == b;",
+                true,
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse_program(input).unwrap();
+            let mut env = Environment::new();
+            let results = eval_program(&program, &mut env).unwrap();
+            assert_eq!(results.inspect(), expected.to_string(), "{}", input);
+        }
+    }
+
     #[test]
     fn test_hash_index_expressions() {
         let tests = vec![
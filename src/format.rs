@@ -0,0 +1,323 @@
+use crate::ast::{is_lone_if, Expr, Opcode, Program, Stmt};
+
+const INDENT: &str = "  ";
+
+// Writes a program back out as source: one statement per line, blocks indented, single spaces
+// around operators, and parentheses only where the grammar needs them. Formatting the output
+// again gives the same text.
+//
+// NOTE: Comments aren't part of the syntax tree, so they're dropped.
+pub fn format_program(program: &Program) -> String {
+    let mut out = String::new();
+    for stmt in &program.statements {
+        write_stmt(stmt, 0, &mut out);
+        out.push('\n');
+    }
+    out
+}
+
+fn write_stmt(stmt: &Stmt, depth: usize, out: &mut String) {
+    match stmt {
+        Stmt::Let { name, value, .. } => {
+            out.push_str(&format!("let {} = ", name));
+            write_expr(value, depth, out);
+            out.push(';');
+        }
+        Stmt::LetDestructure { names, value, .. } => {
+            out.push_str(&format!("let [{}] = ", names.join(", ")));
+            write_expr(value, depth, out);
+            out.push(';');
+        }
+        Stmt::Const { name, value, .. } => {
+            out.push_str(&format!("const {} = ", name));
+            write_expr(value, depth, out);
+            out.push(';');
+        }
+        Stmt::Assign { name, value, .. } => {
+            out.push_str(&format!("{} = ", name));
+            write_expr(value, depth, out);
+            out.push(';');
+        }
+        Stmt::Return { return_value } => {
+            out.push_str("return ");
+            write_expr(return_value, depth, out);
+            out.push(';');
+        }
+        Stmt::Expr { expression } => {
+            write_expr(expression, depth, out);
+            out.push(';');
+        }
+        Stmt::Block { statements } => write_block(statements, depth, out),
+        Stmt::While { condition, body } => {
+            out.push_str("while (");
+            write_expr(condition, depth, out);
+            out.push_str(") ");
+            write_stmt(body, depth, out);
+        }
+    }
+}
+
+fn write_block(statements: &[Box<Stmt>], depth: usize, out: &mut String) {
+    out.push_str("{\n");
+    for stmt in statements {
+        out.push_str(&INDENT.repeat(depth + 1));
+        write_stmt(stmt, depth + 1, out);
+        out.push('\n');
+    }
+    out.push_str(&INDENT.repeat(depth));
+    out.push('}');
+}
+
+// How loosely an expression binds, following the precedence levels in the grammar. Expressions
+// that start with a keyword extend as far right as they can, so they bind loosest of all.
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::InfixOp { operator, .. } => match operator {
+            Opcode::Mul | Opcode::Div => 3,
+            Opcode::Add | Opcode::Sub => 4,
+            Opcode::Shl | Opcode::Shr => 5,
+            Opcode::Lt | Opcode::Gt => 6,
+            Opcode::Eq | Opcode::NotEq => 7,
+            Opcode::BitAnd => 8,
+            Opcode::BitXor => 9,
+            Opcode::BitOr => 10,
+            Opcode::Bang => unreachable!("`!` is only a prefix operator"),
+        },
+        Expr::If { .. } | Expr::With { .. } | Expr::Try { .. } | Expr::LetIn { .. } => 11,
+        _ => 0,
+    }
+}
+
+// Whether the expression can be called or indexed as it is, without parentheses.
+fn is_postfix_operand(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Identifier(..)
+            | Expr::FuncLit { .. }
+            | Expr::ArrayLit { .. }
+            | Expr::HashLit { .. }
+            | Expr::Call { .. }
+            | Expr::Index { .. }
+    )
+}
+
+fn write_operand(expr: &Expr, parenthesize: bool, depth: usize, out: &mut String) {
+    if parenthesize {
+        out.push('(');
+        write_expr(expr, depth, out);
+        out.push(')');
+    } else {
+        write_expr(expr, depth, out);
+    }
+}
+
+fn write_list(exprs: &[Box<Expr>], depth: usize, out: &mut String) {
+    for (i, expr) in exprs.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_expr(expr, depth, out);
+    }
+}
+
+fn write_expr(expr: &Expr, depth: usize, out: &mut String) {
+    match expr {
+        Expr::Number(n) => out.push_str(&n.to_string()),
+        Expr::Identifier(name, _) => out.push_str(name),
+        Expr::Boolean(b) => out.push_str(&b.to_string()),
+        Expr::StringLit(s) => write_string(s, out),
+        // Operators are left-associative, so a right operand at the same level needs
+        // parentheses to keep its grouping.
+        Expr::InfixOp {
+            left,
+            operator,
+            right,
+            ..
+        } => {
+            let level = precedence(expr);
+            write_operand(left, precedence(left) > level, depth, out);
+            out.push_str(&format!(" {} ", operator.as_str()));
+            write_operand(right, precedence(right) >= level, depth, out);
+        }
+        Expr::PrefixOp {
+            operator, right, ..
+        } => {
+            out.push_str(operator.as_str());
+            write_operand(right, precedence(right) > 0, depth, out);
+        }
+        Expr::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            out.push_str("if (");
+            write_expr(condition, depth, out);
+            out.push_str(") ");
+            write_stmt(consequence, depth, out);
+            match alternative.as_deref() {
+                Some(Stmt::Block { statements }) if is_lone_if(statements) => {
+                    out.push_str(" else ");
+                    if let Stmt::Expr { expression } = statements[0].as_ref() {
+                        write_expr(expression, depth, out);
+                    }
+                }
+                Some(alt) => {
+                    out.push_str(" else ");
+                    write_stmt(alt, depth, out);
+                }
+                None => {}
+            }
+        }
+        Expr::With { bindings, body } => {
+            out.push_str("with (");
+            for (i, (name, value)) in bindings.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                out.push_str(&format!("{} = ", name));
+                write_expr(value, depth, out);
+            }
+            out.push_str(") ");
+            write_stmt(body, depth, out);
+        }
+        Expr::Try { body, cleanup } => {
+            out.push_str("try ");
+            write_stmt(body, depth, out);
+            out.push_str(" finally ");
+            write_stmt(cleanup, depth, out);
+        }
+        Expr::LetIn { name, value, body } => {
+            out.push_str(&format!("let {} = ", name));
+            write_expr(value, depth, out);
+            out.push_str(" in ");
+            write_expr(body, depth, out);
+        }
+        Expr::FuncLit { parameters, body } => {
+            out.push_str("fn(");
+            write_list(parameters, depth, out);
+            out.push_str(") ");
+            write_stmt(body, depth, out);
+        }
+        Expr::Call {
+            function,
+            arguments,
+            ..
+        } => {
+            write_operand(function, !is_postfix_operand(function), depth, out);
+            out.push('(');
+            write_list(arguments, depth, out);
+            out.push(')');
+        }
+        Expr::ArrayLit { elements } => {
+            out.push('[');
+            write_list(elements, depth, out);
+            out.push(']');
+        }
+        Expr::Index { left, index, .. } => {
+            write_operand(left, !is_postfix_operand(left), depth, out);
+            out.push('[');
+            write_expr(index, depth, out);
+            out.push(']');
+        }
+        Expr::HashLit { pairs, .. } => {
+            out.push('{');
+            for (i, (key, value)) in pairs.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_expr(key, depth, out);
+                out.push_str(": ");
+                write_expr(value, depth, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+// Only the escapes `parser::unquote` understands are used, so the literal reads back the same.
+fn write_string(value: &str, out: &mut String) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    fn format_source(input: &str) -> String {
+        format_program(&parse_program(input).unwrap())
+    }
+
+    #[test]
+    fn test_format_program() {
+        let tests = vec![
+            ("let   x=1+2*3;", "let x = 1 + 2 * 3;\n"),
+            ("let x = (1 + 2) * 3;", "let x = (1 + 2) * 3;\n"),
+            ("a - (b - c); (a - b) - c;", "a - (b - c);\na - b - c;\n"),
+            ("-(a + b); !-x;", "-(a + b);\n!-x;\n"),
+            (
+                "let f = fn(x, y) { x + y; }; f(1, 2);",
+                "let f = fn(x, y) {\n  x + y;\n};\nf(1, 2);\n",
+            ),
+            (
+                "if (x > 1) { return 1; } else if (x > 0) { 0; } else { -1; };",
+                "if (x > 1) {\n  return 1;\n} else if (x > 0) {\n  0;\n} else {\n  -1;\n};\n",
+            ),
+            (
+                "while (i < 3) { i = i + 1; }",
+                "while (i < 3) {\n  i = i + 1;\n}\n",
+            ),
+            (
+                "let [a, b] = [1, {\"k\": [2]}]; a[0][1]; fn() { 1; }();",
+                "let [a, b] = [1, {\"k\": [2]}];\na[0][1];\nfn() {\n  1;\n}();\n",
+            ),
+            (
+                "(let x = 1 in x) + (with (y = 2) { y; }); (if (a) { 1; })(2);",
+                "(let x = 1 in x) + (with (y = 2) {\n  y;\n});\n(if (a) {\n  1;\n})(2);\n",
+            ),
+            (
+                "try { f(); } finally { const z = \"a\\\"b\\n\"; };",
+                "try {\n  f();\n} finally {\n  const z = \"a\\\"b\\n\";\n};\n",
+            ),
+            ("{ let a = 1; }", "{\n  let a = 1;\n}\n"),
+            ("// gone\nx; /* also gone */", "x;\n"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(format_source(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_format_is_stable() {
+        let inputs = vec![
+            include_str!("prelude.mk"),
+            "let fib = fn(n) { if (n < 2) { n; } else { fib(n - 1) + fib(n - 2); }; }; fib(10);",
+            "let x = 1 - (2 - (3 - 4)) * -(5 + 6) << 2 & 3 ^ 4 | 5 == 6 != (7 < 8);",
+            "let m = {1: [fn(a) { a; }], \"b\": {true: \"\\t\"}}; m[\"b\"][true];",
+            "let f = fn(x) { let y = let z = x in z * 2; with (a = y, b = a) { try { a + b; } finally { 1; }; }; };",
+            "let n = if (x) { 1; } else { if (y) { 2; }; };",
+        ];
+
+        for input in inputs {
+            let program = parse_program(input).unwrap();
+            let formatted = format_program(&program);
+            let reparsed =
+                parse_program(&formatted).unwrap_or_else(|e| panic!("{}\n{}", e, formatted));
+            // The syntax tree survives the round trip, and formatting again changes nothing.
+            assert_eq!(format!("{:?}", reparsed), format!("{:?}", program));
+            assert_eq!(format_program(&reparsed), formatted);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const HISTORY_FILE: &str = ".monkey_history";
+
+pub struct History {
+    path: Option<PathBuf>,
+    entries: Vec<String>,
+}
+
+impl History {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let entries = path
+            .as_deref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .map(|contents| contents.lines().map(|l| l.to_string()).collect())
+            .unwrap_or_default();
+        History { path, entries }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    pub fn append(&mut self, line: &str) -> io::Result<()> {
+        self.entries.push(line.to_string());
+        match self.path {
+            Some(ref path) => append_line(path, line),
+            None => Ok(()),
+        }
+    }
+}
+
+fn append_line(path: &Path, line: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)
+}
+
+// NOTE: History is kept in memory only when $HOME is not set.
+pub fn default_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_and_load() {
+        let path = std::env::temp_dir().join(format!("monkey_history_test_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut history = History::load(Some(path.clone()));
+        assert!(history.entries().is_empty());
+        history.append("let a = 1;").unwrap();
+        history.append("a + 1;").unwrap();
+        assert_eq!(history.entries(), ["let a = 1;", "a + 1;"]);
+
+        let mut reloaded = History::load(Some(path.clone()));
+        assert_eq!(reloaded.entries(), ["let a = 1;", "a + 1;"]);
+        reloaded.append("a;").unwrap();
+        assert_eq!(
+            History::load(Some(path.clone())).entries(),
+            ["let a = 1;", "a + 1;", "a;"]
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_in_memory_history() {
+        let mut history = History::load(None);
+        history.append("1;").unwrap();
+        assert_eq!(history.entries(), ["1;"]);
+    }
+}
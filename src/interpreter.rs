@@ -0,0 +1,191 @@
+use crate::environment::Environment;
+use crate::evaluator::eval_program;
+use crate::object::{set_hash_seed, ObjectRef};
+use crate::parser::parse_program;
+use crate::trace::Trace;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::rc::Rc;
+
+/// Parses and evaluates source against an environment that persists between runs. The
+/// environment starts out with the prelude loaded.
+pub struct Interpreter {
+    env: Environment,
+    trace: Option<Rc<Trace>>,
+    hash_seed: u64,
+}
+
+impl Interpreter {
+    /// Uses a random seed for hashing hash keys.
+    pub fn new() -> Self {
+        Interpreter::with_hash_seed(RandomState::new().build_hasher().finish())
+    }
+
+    pub fn with_hash_seed(hash_seed: u64) -> Self {
+        set_hash_seed(hash_seed);
+        Interpreter {
+            env: Environment::new_with_prelude(),
+            trace: None,
+            hash_seed,
+        }
+    }
+
+    // NOTE: Runtime errors are returned as `Ok` with an Error object, just like `eval_program`;
+    // only a parse error is an `Err`.
+    pub fn run(&mut self, src: &str) -> Result<ObjectRef, String> {
+        let program = parse_program(src)?;
+        // NOTE: Other interpreters on this thread may have changed the seed since the last run,
+        // and hashes made in this one should use its own.
+        set_hash_seed(self.hash_seed);
+        eval_program(&program, &mut self.env)
+    }
+
+    /// Drops every binding made so far, keeping the environment's config and the prelude.
+    pub fn reset(&mut self) {
+        self.env = Environment::with_config(self.env.config());
+        set_hash_seed(self.hash_seed);
+        self.env.load_prelude();
+        self.set_tracing(self.trace.is_some());
+    }
+
+    /// Turns recording of every evaluated expression on or off, see `take_trace`.
+    pub fn set_tracing(&mut self, on: bool) {
+        self.trace = on.then(|| Rc::new(Trace::new()));
+        let observer = self.trace.clone().map(|trace| trace as Rc<_>);
+        self.env.set_observer(observer);
+    }
+
+    /// The `(expression, value)` pairs recorded since the last call, in evaluation order.
+    pub fn take_trace(&mut self) -> Vec<(String, String)> {
+        self.trace
+            .as_ref()
+            .map(|trace| trace.take())
+            .unwrap_or_default()
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_carries_over_between_runs() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .run("let add = fn(a, b) { a + b; }; let x = 2;")
+            .unwrap();
+        assert_eq!(interpreter.run("add(x, 3);").unwrap().inspect(), "5");
+        interpreter.run("x = 10;").unwrap();
+        assert_eq!(interpreter.run("add(x, 3);").unwrap().inspect(), "13");
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut interpreter = Interpreter::new();
+        assert!(interpreter.run("let = ;").is_err());
+        assert_eq!(
+            interpreter.run("1 + true;").unwrap().inspect(),
+            "type mismatch: INTEGER + BOOLEAN (line 1)"
+        );
+        assert_eq!(interpreter.run("1 + 1;").unwrap().inspect(), "2");
+    }
+
+    #[test]
+    fn test_hash_seed_does_not_change_output() {
+        let src =
+            "let h = {\"b\": 1, 2: true, \"a\": [1], false: \"x\", 1: \"one\", true: \"yes\"}; \
+                   let h = delete(set(h, \"c\", 3), 2); \
+                   [h, h[\"a\"], h[1], h[true], h[2], keys(h)];";
+        let mut first = Interpreter::with_hash_seed(1);
+        let mut second = Interpreter::with_hash_seed(2);
+        let expected =
+            "[{\"b\": 1, \"a\": [1], false: \"x\", 1: \"one\", true: \"yes\", \"c\": 3}, \
+                        [1], \"one\", \"yes\", null, [\"b\", \"a\", false, 1, true, \"c\"]]";
+
+        // Interleaved, so each run has to put its own seed back.
+        first.run("let g = {\"k\": 1};").unwrap();
+        assert_eq!(second.run(src).unwrap().inspect(), expected);
+        assert_eq!(first.run(src).unwrap().inspect(), expected);
+        assert_eq!(first.run("g[\"k\"];").unwrap().inspect(), "1");
+    }
+
+    #[test]
+    fn test_hashes_outlive_seed_changes() {
+        let mut env = Environment::new();
+        let program = parse_program("let h = {\"a\": 1, 2: true}; h[\"a\"];").unwrap();
+        assert_eq!(eval_program(&program, &mut env).unwrap().inspect(), "1");
+
+        let mut first = Interpreter::with_hash_seed(1);
+        let from_first = first.run("{\"a\": 1, 2: true};").unwrap();
+        let _ = Interpreter::with_hash_seed(2);
+
+        let program = parse_program("[h[\"a\"], h[2], set(h, \"a\", 3)];").unwrap();
+        assert_eq!(
+            eval_program(&program, &mut env).unwrap().inspect(),
+            "[1, true, {\"a\": 3, 2: true}]"
+        );
+        env.set("g".to_string(), from_first);
+        let program = parse_program("[g[\"a\"], g[2], delete(g, 2)];").unwrap();
+        assert_eq!(
+            eval_program(&program, &mut env).unwrap().inspect(),
+            "[1, true, {\"a\": 1}]"
+        );
+    }
+
+    #[test]
+    fn test_deep_recursion_on_a_default_thread() {
+        let result = std::thread::spawn(|| {
+            let mut interpreter = Interpreter::new();
+            let src = "let f = fn(n) { if (n == 0) { 0; } else { 1 + f(n - 1); }; }; f(999);";
+            interpreter.run(src).unwrap().inspect()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(result, "999");
+    }
+
+    #[test]
+    fn test_tracing() {
+        let mut interpreter = Interpreter::new();
+        interpreter.run("1 + 1;").unwrap();
+        assert!(interpreter.take_trace().is_empty());
+
+        interpreter.set_tracing(true);
+        interpreter.run("1 + 2 * 3;").unwrap();
+        let values: Vec<String> = interpreter
+            .take_trace()
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect();
+        assert_eq!(values, vec!["1", "2", "3", "6", "7"]);
+
+        interpreter.reset();
+        interpreter.run("2;").unwrap();
+        assert_eq!(
+            interpreter.take_trace(),
+            vec![("2".to_string(), "2".to_string())]
+        );
+
+        interpreter.set_tracing(false);
+        interpreter.run("2;").unwrap();
+        assert!(interpreter.take_trace().is_empty());
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut interpreter = Interpreter::new();
+        interpreter.run("let x = 1; let sum = 2;").unwrap();
+        interpreter.reset();
+        assert_eq!(
+            interpreter.run("x;").unwrap().inspect(),
+            "identifier not found: x (line 1)"
+        );
+        assert_eq!(interpreter.run("sum([1, 2]);").unwrap().inspect(), "3");
+    }
+}
@@ -0,0 +1,173 @@
+use crate::downcast_ref;
+use crate::object::{Array, Boolean, Hash, Integer, Null, ObjectRef, StringObj};
+use std::collections::HashSet;
+
+const INDENT: &str = "  ";
+
+// Serializes a value for hosts that want results as data. Integers, booleans, strings, null,
+// arrays and hashes map onto their JSON counterparts; functions and builtins are an error.
+pub fn to_json(object: &ObjectRef) -> Result<String, String> {
+    let mut out = String::new();
+    write_value(object, false, 0, &mut out)?;
+    Ok(out)
+}
+
+pub fn to_json_pretty(object: &ObjectRef) -> Result<String, String> {
+    let mut out = String::new();
+    write_value(object, true, 0, &mut out)?;
+    Ok(out)
+}
+
+fn write_value(
+    object: &ObjectRef,
+    pretty: bool,
+    depth: usize,
+    out: &mut String,
+) -> Result<(), String> {
+    if let Some(integer) = downcast_ref!(object, Integer) {
+        out.push_str(&integer.value.to_string());
+    } else if let Some(boolean) = downcast_ref!(object, Boolean) {
+        out.push_str(&boolean.value.to_string());
+    } else if downcast_ref!(object, Null).is_some() {
+        out.push_str("null");
+    } else if let Some(string) = downcast_ref!(object, StringObj) {
+        write_string(&string.value, out);
+    } else if let Some(array) = downcast_ref!(object, Array) {
+        let items = array
+            .elements
+            .iter()
+            .map(|e| {
+                let mut item = String::new();
+                write_value(e, pretty, depth + 1, &mut item)?;
+                Ok(item)
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        write_container('[', ']', items, pretty, depth, out);
+    } else if let Some(hash) = downcast_ref!(object, Hash) {
+        // NOTE: Non-string keys are written using their inspect form, and members keep the
+        // hash's insertion order. Keys like `1` and `"1"` would then be written the same, so
+        // a hash with both is an error rather than an object with a duplicate member.
+        let mut members = Vec::new();
+        let mut seen = HashSet::new();
+        for pair in hash.pairs.values() {
+            let key = match downcast_ref!(pair.key, StringObj) {
+                Some(string) => string.value.clone(),
+                None => pair.key.inspect(),
+            };
+            if !seen.insert(key.clone()) {
+                return Err(format!(
+                    "cannot serialize HASH to JSON: key \"{}\" appears more than once",
+                    key
+                ));
+            }
+            let mut value = String::new();
+            write_value(&pair.value, pretty, depth + 1, &mut value)?;
+            members.push((key, value));
+        }
+        let items = members
+            .into_iter()
+            .map(|(key, value)| {
+                let mut item = String::new();
+                write_string(&key, &mut item);
+                item.push(':');
+                if pretty {
+                    item.push(' ');
+                }
+                item.push_str(&value);
+                item
+            })
+            .collect();
+        write_container('{', '}', items, pretty, depth, out);
+    } else {
+        return Err(format!(
+            "cannot serialize {} to JSON",
+            object.object_type().as_str()
+        ));
+    }
+    Ok(())
+}
+
+fn write_container(
+    open: char,
+    close: char,
+    items: Vec<String>,
+    pretty: bool,
+    depth: usize,
+    out: &mut String,
+) {
+    out.push(open);
+    if pretty && !items.is_empty() {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push('\n');
+            out.push_str(&INDENT.repeat(depth + 1));
+            out.push_str(item);
+        }
+        out.push('\n');
+        out.push_str(&INDENT.repeat(depth));
+    } else {
+        out.push_str(&items.join(","));
+    }
+    out.push(close);
+}
+
+fn write_string(value: &str, out: &mut String) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::box_it;
+    use crate::environment::Environment;
+    use crate::evaluator::eval_program;
+    use crate::parser::parse_program;
+
+    #[test]
+    fn test_string_escapes() {
+        let object: ObjectRef = box_it!(StringObj {
+            value: "a\"b\\c\nd\te\u{1}".to_string(),
+        });
+        assert_eq!(to_json(&object).unwrap(), "\"a\\\"b\\\\c\\nd\\te\\u0001\"");
+    }
+
+    #[test]
+    fn test_nested_values() {
+        let program =
+            parse_program("{\"xs\": [1, [true, first([])]], 2: {\"s\": \"t\"}, \"e\": []};")
+                .unwrap();
+        let object = eval_program(&program, &mut Environment::new()).unwrap();
+        assert_eq!(
+            to_json(&object).unwrap(),
+            "{\"xs\":[1,[true,null]],\"2\":{\"s\":\"t\"},\"e\":[]}"
+        );
+
+        let program = parse_program("{1: \"a\", \"1\": \"b\"};").unwrap();
+        let object = eval_program(&program, &mut Environment::new()).unwrap();
+        assert_eq!(
+            to_json(&object).unwrap_err(),
+            "cannot serialize HASH to JSON: key \"1\" appears more than once"
+        );
+
+        let program = parse_program("[1, {\"f\": fn() { 1; }}];").unwrap();
+        let object = eval_program(&program, &mut Environment::new()).unwrap();
+        assert_eq!(
+            to_json(&object).unwrap_err(),
+            "cannot serialize FUNCTION to JSON"
+        );
+    }
+}
@@ -0,0 +1,18 @@
+#![allow(clippy::vec_box)]
+
+pub mod ast;
+pub mod builtin;
+pub mod check;
+pub mod environment;
+pub mod evaluator;
+pub mod format;
+#[cfg(feature = "io")]
+pub mod history;
+pub mod interpreter;
+pub mod json;
+pub mod lint;
+pub mod object;
+pub mod parser;
+pub mod trace;
+mod utils;
+pub mod visitor;
@@ -0,0 +1,264 @@
+use crate::ast::{Expr, Program, Span, Stmt};
+use crate::visitor::{walk, walk_expr, walk_stmt, Visitor};
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub name: String,
+    // The line of the `let` statement that makes the binding.
+    pub line: usize,
+}
+
+struct Binding {
+    name: String,
+    // Parameters and other bindings that are never reported have no span.
+    span: Option<Span>,
+    used: bool,
+}
+
+// Function bodies and `with` expressions are the only constructs that open a new scope; blocks
+// of `if` and `while` bind into the enclosing one, exactly like the evaluator does.
+#[derive(Default)]
+struct Scope {
+    bindings: Vec<Binding>,
+    captured: HashSet<String>,
+}
+
+struct UnusedLet {
+    scopes: Vec<Scope>,
+    // Names referenced inside each enclosing `while`, innermost last.
+    loops: Vec<HashSet<String>>,
+    // Each warning with the offset of its statement, to sort them in source order.
+    warnings: Vec<(usize, Warning)>,
+}
+
+impl UnusedLet {
+    fn declare(&mut self, name: &str, span: Option<Span>) {
+        let scope = self.scopes.last_mut().unwrap();
+        scope.bindings.push(Binding {
+            name: name.to_string(),
+            span,
+            used: span.is_none(),
+        });
+    }
+
+    fn reference(&mut self, name: &str) {
+        for refs in self.loops.iter_mut() {
+            refs.insert(name.to_string());
+        }
+        let innermost = self.scopes.len() - 1;
+        for (depth, scope) in self.scopes.iter_mut().enumerate().rev() {
+            // Closures may run after later rebindings, so a captured name is never reported
+            // in the enclosing scopes.
+            if depth != innermost {
+                scope.captured.insert(name.to_string());
+            }
+            if let Some(binding) = scope.bindings.iter_mut().rev().find(|b| b.name == name) {
+                binding.used = true;
+                return;
+            }
+        }
+    }
+
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        for binding in scope.bindings {
+            if let (false, Some(span)) = (binding.used, binding.span) {
+                if !scope.captured.contains(&binding.name) {
+                    let warning = Warning {
+                        name: binding.name,
+                        line: span.line,
+                    };
+                    self.warnings.push((span.start, warning));
+                }
+            }
+        }
+    }
+}
+
+impl Visitor for UnusedLet {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let {
+                ref name,
+                ref value,
+                span,
+            }
+            | Stmt::Const {
+                ref name,
+                ref value,
+                span,
+            } => {
+                self.visit_expr(value);
+                self.declare(name, Some(*span));
+            }
+            Stmt::LetDestructure {
+                ref names,
+                ref value,
+                span,
+            } => {
+                self.visit_expr(value);
+                for name in names.iter().filter(|name| *name != "_") {
+                    self.declare(name, Some(*span));
+                }
+            }
+            // NOTE: Assigning to a binding conservatively counts as using it.
+            Stmt::Assign {
+                ref name,
+                ref value,
+                ..
+            } => {
+                self.visit_expr(value);
+                self.reference(name);
+            }
+            Stmt::While { .. } => {
+                let start = self.scopes.last().unwrap().bindings.len();
+                self.loops.push(HashSet::new());
+                walk_stmt(self, stmt);
+                // A binding made in the body is visible to the next iteration, so any
+                // reference inside the loop counts as a use.
+                let refs = self.loops.pop().unwrap();
+                let scope = self.scopes.last_mut().unwrap();
+                for binding in scope.bindings[start..].iter_mut() {
+                    if refs.contains(&binding.name) {
+                        binding.used = true;
+                    }
+                }
+            }
+            _ => walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Identifier(ref name, _) => self.reference(name),
+            Expr::FuncLit {
+                ref parameters,
+                ref body,
+            } => {
+                self.scopes.push(Scope::default());
+                for param in parameters {
+                    if let Expr::Identifier(ref name, _) = **param {
+                        self.declare(name, None);
+                    }
+                }
+                self.visit_stmt(body);
+                self.pop_scope();
+            }
+            // NOTE: Like parameters, `with` and `let ... in` bindings are never reported.
+            Expr::With {
+                ref bindings,
+                ref body,
+            } => {
+                self.scopes.push(Scope::default());
+                for (name, value) in bindings {
+                    self.visit_expr(value);
+                    self.declare(name, None);
+                }
+                self.visit_stmt(body);
+                self.pop_scope();
+            }
+            Expr::LetIn {
+                ref name,
+                ref value,
+                ref body,
+            } => {
+                self.scopes.push(Scope::default());
+                self.visit_expr(value);
+                self.declare(name, None);
+                self.visit_expr(body);
+                self.pop_scope();
+            }
+            _ => walk_expr(self, expr),
+        }
+    }
+}
+
+/// Reports `let` bindings that are never referenced in their scope.
+pub fn lint(program: &Program) -> Vec<Warning> {
+    let mut pass = UnusedLet {
+        scopes: vec![Scope::default()],
+        loops: Vec::new(),
+        warnings: Vec::new(),
+    };
+    walk(&mut pass, program);
+    pass.pop_scope();
+    pass.warnings.sort_by_key(|(start, _)| *start);
+    pass.warnings
+        .into_iter()
+        .map(|(_, warning)| warning)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    fn unused_names(input: &str) -> Vec<(String, usize)> {
+        let program = parse_program(input).unwrap();
+        lint(&program)
+            .into_iter()
+            .map(|w| (w.name, w.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_unused_binding() {
+        assert_eq!(
+            unused_names("let a = 1;\nlet b = 2;\nb;"),
+            vec![("a".to_string(), 1)]
+        );
+        assert_eq!(
+            unused_names("let f = fn(x) {\n  let y = x;\n  x;\n};\nf(1);"),
+            vec![("y".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn test_used_binding() {
+        assert!(unused_names("let a = 1; a;").is_empty());
+        assert!(unused_names("let a = 1; if (a > 0) { a; };").is_empty());
+        assert!(unused_names("const a = 1; a;").is_empty());
+        assert!(unused_names("let i = 0; while (i < 3) { let i = i + 1; }").is_empty());
+        assert_eq!(
+            unused_names("while (true) {\n  let t = 1;\n}"),
+            vec![("t".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn test_destructured_binding() {
+        assert_eq!(
+            unused_names("let [a, b, _] = [1, 2, 3];\na;"),
+            vec![("b".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_shadowed_binding() {
+        assert_eq!(
+            unused_names("let a = 1;\nlet a = 2;\na;"),
+            vec![("a".to_string(), 1)]
+        );
+        assert_eq!(
+            unused_names("let b = 1;\nlet a = 1;\nlet f = fn() { let a = 2; a; };\nf();"),
+            vec![("b".to_string(), 1), ("a".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn test_with_scope() {
+        assert_eq!(
+            unused_names("with (x = 1) {\n  let y = x;\n  2;\n};"),
+            vec![("y".to_string(), 2)]
+        );
+        assert!(unused_names("let a = 1; with (x = a) { x; };").is_empty());
+    }
+
+    #[test]
+    fn test_captured_binding() {
+        assert!(unused_names("let x = 1; let f = fn() { x; }; let x = 2; f();").is_empty());
+        assert!(unused_names("let f = fn(n) { f(n); }; f(1);").is_empty());
+    }
+}
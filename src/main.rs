@@ -1,20 +1,81 @@
-mod ast;
-mod builtin;
-mod environment;
-mod evaluator;
-mod object;
-mod parser;
-mod utils;
-
-use parser::parse_program;
+use monkey_rs::history::{self, History};
+use monkey_rs::interpreter::Interpreter;
+use monkey_rs::object::ObjectType;
 use std::io::{self, Write};
+use std::process;
+use std::{env, fs};
+
+const USAGE: &str = "usage: monkey-rs [--version | --eval <source> | <file>]";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Repl,
+    Version,
+    Eval(String),
+    File(String),
+}
+
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    match args {
+        [] => Ok(Command::Repl),
+        [flag] if flag == "--version" => Ok(Command::Version),
+        [flag] if flag == "--eval" => Err("`--eval` needs a source string".to_string()),
+        [flag, source] if flag == "--eval" => Ok(Command::Eval(source.clone())),
+        [arg] if arg.starts_with("--") => Err(format!("unknown option `{}`", arg)),
+        [path] => Ok(Command::File(path.clone())),
+        _ => Err("too many arguments".to_string()),
+    }
+}
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = match parse_args(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    match command {
+        Command::Repl => repl(),
+        Command::Version => println!("monkey-rs {}", env!("CARGO_PKG_VERSION")),
+        Command::Eval(source) => run_source(&source),
+        Command::File(path) => match fs::read_to_string(&path) {
+            Ok(source) => run_source(&source),
+            Err(e) => {
+                eprintln!("could not read {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+    }
+}
+
+// Runs a whole program, exiting with a non-zero status if it fails to parse or evaluates to an
+// error. Output only comes from the program itself, e.g. through `puts`.
+fn run_source(source: &str) {
+    match Interpreter::new().run(source) {
+        Ok(obj) if obj.object_type() == ObjectType::Error => {
+            eprintln!("{}", obj.inspect());
+            process::exit(1);
+        }
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn repl() {
     println!("Welcome to the REPL!");
     println!("Type 'exit' to exit.");
+    println!("Type ':history' to list previous inputs.");
+    println!("Type ':trace on' or ':trace off' to show each step of evaluation.");
 
     let mut input = String::new();
-    let mut env = environment::Environment::new();
+    let mut interpreter = Interpreter::new();
+    let mut history = History::load(history::default_path());
 
     loop {
         print!("> ");
@@ -31,23 +92,64 @@ fn main() {
             break;
         }
 
-        let program = parse_program(trimmed_input);
-
-        match program {
-            Ok(program) => {
-                let results = evaluator::eval_program(&program, &mut env);
+        if trimmed_input == ":history" {
+            for (i, line) in history.entries().iter().enumerate() {
+                println!("{:>4}  {}", i + 1, line);
+            }
+            continue;
+        }
 
-                match results {
-                    Ok(obj) => println!("{}", obj.inspect()),
-                    Err(e) => println!("Error: {}", e),
-                }
+        if let Some(setting) = trimmed_input.strip_prefix(":trace") {
+            match setting.trim() {
+                "on" => interpreter.set_tracing(true),
+                "off" => interpreter.set_tracing(false),
+                _ => println!("usage: :trace on|off"),
             }
-            Err(e) => {
-                println!("Parse Error: {}", e);
-                continue;
+            continue;
+        }
+
+        if !trimmed_input.is_empty() {
+            if let Err(e) = history.append(trimmed_input) {
+                println!("History Error: {}", e);
             }
         }
+
+        let result = interpreter.run(trimmed_input);
+        for (expr, value) in interpreter.take_trace() {
+            println!("  {} => {}", expr, value);
+        }
+        match result {
+            Ok(obj) => println!("{}", obj.inspect()),
+            Err(e) => println!("{}", e),
+        }
     }
 
     println!("Goodbye!");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_args(&args(&[])), Ok(Command::Repl));
+        assert_eq!(parse_args(&args(&["--version"])), Ok(Command::Version));
+        assert_eq!(
+            parse_args(&args(&["--eval", "puts(1 + 2);"])),
+            Ok(Command::Eval("puts(1 + 2);".to_string()))
+        );
+        assert_eq!(
+            parse_args(&args(&["script.mk"])),
+            Ok(Command::File("script.mk".to_string()))
+        );
+        assert!(parse_args(&args(&["--eval"])).is_err());
+        assert!(parse_args(&args(&["--help"])).is_err());
+        assert!(parse_args(&args(&["a.mk", "b.mk"])).is_err());
+        assert!(parse_args(&args(&["--version", "x"])).is_err());
+    }
+}
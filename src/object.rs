@@ -1,35 +1,26 @@
 use crate::ast::Expr;
 use crate::{ast::Stmt, environment::Environment};
+use indexmap::IndexMap;
+use siphasher::sip::SipHasher13;
 use std::any::Any;
-use std::collections::HashMap;
+use std::cell::Cell;
+use std::hash::BuildHasher;
+use std::rc::Rc;
 
-#[allow(dead_code)]
-pub trait Object: ObjectClone {
+pub trait Object {
     fn as_any(&self) -> &dyn Any;
     fn object_type(&self) -> ObjectType;
     fn inspect(&self) -> String;
-}
-
-pub trait ObjectClone {
-    fn clone_box(&self) -> ObjectRef;
-}
-
-impl<T> ObjectClone for T
-where
-    T: 'static + Object + Clone,
-{
-    fn clone_box(&self) -> ObjectRef {
-        Box::new(self.clone())
-    }
-}
 
-impl Clone for ObjectRef {
-    fn clone(&self) -> ObjectRef {
-        self.clone_box()
+    // How the value is shown as an element of an array or hash. Functions are cut down to their
+    // parameters there, so their bodies don't swamp the rest of the collection.
+    fn inspect_nested(&self) -> String {
+        self.inspect()
     }
 }
 
-pub type ObjectRef = Box<dyn Object>;
+// Objects are never changed once made, so copies of a value share one allocation.
+pub type ObjectRef = Rc<dyn Object>;
 
 const INTEGER_OBJ: &str = "INTEGER";
 const NULL_OBJ: &str = "NULL";
@@ -56,7 +47,6 @@ pub enum ObjectType {
     Hash,
 }
 
-#[allow(dead_code)]
 impl ObjectType {
     pub fn as_str(&self) -> &str {
         match self {
@@ -144,9 +134,50 @@ impl Object for ReturnValue {
     }
 }
 
+// What went wrong, so hosts (and the `error_kind` builtin) can tell errors apart without
+// matching on their messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    TypeMismatch,
+    UnknownOperator,
+    UnknownIdentifier,
+    DivideByZero,
+    Arity,
+    Argument,
+    IndexOutOfRange,
+    Constant,
+    // Only found by `check::validate`: a call whose argument count doesn't match the function's
+    // parameters. Such a call still runs, extra arguments are dropped and missing ones unbound.
+    ArgumentCount,
+    // Raised by a script itself, through the `error` builtin.
+    User,
+    Other,
+}
+
+impl ErrorKind {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ErrorKind::TypeMismatch => "TYPE_MISMATCH",
+            ErrorKind::UnknownOperator => "UNKNOWN_OPERATOR",
+            ErrorKind::UnknownIdentifier => "UNKNOWN_IDENTIFIER",
+            ErrorKind::DivideByZero => "DIVIDE_BY_ZERO",
+            ErrorKind::Arity => "ARITY",
+            ErrorKind::Argument => "ARGUMENT",
+            ErrorKind::IndexOutOfRange => "INDEX_OUT_OF_RANGE",
+            ErrorKind::Constant => "CONSTANT",
+            ErrorKind::ArgumentCount => "ARGUMENT_COUNT",
+            ErrorKind::User => "USER",
+            ErrorKind::Other => "OTHER",
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Error {
     pub message: String,
+    pub kind: ErrorKind,
+    // The line of the innermost node the error was raised in, when it came from source.
+    pub line: Option<usize>,
 }
 
 impl Object for Error {
@@ -158,7 +189,10 @@ impl Object for Error {
     }
 
     fn inspect(&self) -> String {
-        format!("{}", self.message)
+        match self.line {
+            Some(line) => format!("{} (line {})", self.message, line),
+            None => self.message.clone(),
+        }
     }
 }
 
@@ -184,6 +218,35 @@ impl Object for Function {
         }
         format!("fn({}) {:?}", params.join(", "), self.body)
     }
+
+    fn inspect_nested(&self) -> String {
+        let params: Vec<String> = self.parameters.iter().map(|p| format!("{:?}", p)).collect();
+        format!("fn({})", params.join(", "))
+    }
+}
+
+// A function whose first parameter is fixed to `receiver`, as produced by `bind`.
+#[derive(Clone)]
+pub struct BoundFunction {
+    pub function: ObjectRef,
+    pub receiver: ObjectRef,
+}
+
+impl Object for BoundFunction {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn object_type(&self) -> ObjectType {
+        ObjectType::Function
+    }
+
+    fn inspect(&self) -> String {
+        format!("bound {}", self.function.inspect())
+    }
+
+    fn inspect_nested(&self) -> String {
+        format!("bound {}", self.function.inspect_nested())
+    }
 }
 
 #[derive(Clone)]
@@ -199,14 +262,32 @@ impl Object for StringObj {
         ObjectType::StringObj
     }
 
+    // Escapes the value the way a string literal would be written, so that e.g. a newline
+    // doesn't break the output onto two lines.
     fn inspect(&self) -> String {
-        format!("\"{}\"", self.value)
+        let mut s = String::from('"');
+        for c in self.value.chars() {
+            match c {
+                '\n' => s.push_str("\\n"),
+                '\t' => s.push_str("\\t"),
+                '\r' => s.push_str("\\r"),
+                '"' => s.push_str("\\\""),
+                '\\' => s.push_str("\\\\"),
+                c => s.push(c),
+            }
+        }
+        s.push('"');
+        s
     }
 }
 
 #[derive(Clone)]
 pub struct Builtin {
+    pub name: &'static str,
     pub func: fn(Vec<ObjectRef>) -> ObjectRef,
+    // Whether calls pass error arguments in as they are, instead of returning the first error
+    // without calling `func`. Only builtins that inspect errors, like `error_kind`, do this.
+    pub takes_errors: bool,
 }
 
 impl Object for Builtin {
@@ -220,6 +301,10 @@ impl Object for Builtin {
     fn inspect(&self) -> String {
         "builtin function".to_string()
     }
+
+    fn inspect_nested(&self) -> String {
+        format!("builtin({})", self.name)
+    }
 }
 
 #[derive(Clone)]
@@ -238,15 +323,18 @@ impl Object for Array {
     fn inspect(&self) -> String {
         let mut elements = Vec::new();
         for e in &self.elements {
-            elements.push(e.inspect());
+            elements.push(e.inspect_nested());
         }
         format!("[{}]", elements.join(", "))
     }
 }
 
+// What a hash key is compared by: its type and value. The type keeps e.g. `1` and `true` apart.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
-pub struct HashKey {
-    pub value: u64,
+pub enum HashKey {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
 }
 
 #[derive(Clone)]
@@ -255,16 +343,18 @@ pub struct HashPair {
     pub value: ObjectRef,
 }
 
-#[allow(dead_code)]
 pub trait Hashable {
     fn hash_key(&self) -> HashKey;
 }
 
+// Pairs are kept in insertion order, which is the order `inspect` and builtins walk them in.
 #[derive(Clone)]
 pub struct Hash {
-    pub pairs: HashMap<HashKey, HashPair>,
+    pub pairs: HashPairs,
 }
 
+pub type HashPairs = IndexMap<HashKey, HashPair, KeyedState>;
+
 impl Object for Hash {
     fn as_any(&self) -> &dyn Any {
         self
@@ -275,43 +365,74 @@ impl Object for Hash {
 
     fn inspect(&self) -> String {
         let mut pairs = Vec::new();
-        for (_, pair) in &self.pairs {
-            pairs.push(format!("{}: {}", pair.key.inspect(), pair.value.inspect()));
+        for pair in self.pairs.values() {
+            pairs.push(format!(
+                "{}: {}",
+                pair.key.inspect_nested(),
+                pair.value.inspect_nested()
+            ));
         }
         format!("{{{}}}", pairs.join(", "))
     }
 }
 
+// NOTE: Keys are hashed with SipHash under a secret seed, so scripts can't pick keys that
+// collide on purpose. The seed is per thread and is replaced by each interpreter before it
+// evaluates anything (see `set_hash_seed`). Each map keeps the seed it was made with, so
+// changing it doesn't affect existing hashes, and pairs are kept in insertion order, so output
+// doesn't depend on it.
+thread_local! {
+    static HASH_SEED: Cell<(u64, u64)> = const { Cell::new((0, 0)) };
+}
+
+pub fn set_hash_seed(seed: u64) {
+    HASH_SEED.with(|keys| keys.set((seed, seed.rotate_left(32) ^ 0x9e3779b97f4a7c15)));
+}
+
+// Builds the hashers of one map, keyed with the seed that was current when the map was made.
+#[derive(Clone, Debug)]
+pub struct KeyedState {
+    k0: u64,
+    k1: u64,
+}
+
+impl Default for KeyedState {
+    fn default() -> Self {
+        let (k0, k1) = HASH_SEED.with(Cell::get);
+        KeyedState { k0, k1 }
+    }
+}
+
+impl BuildHasher for KeyedState {
+    type Hasher = SipHasher13;
+
+    fn build_hasher(&self) -> SipHasher13 {
+        SipHasher13::new_with_keys(self.k0, self.k1)
+    }
+}
+
 impl Hashable for Integer {
     fn hash_key(&self) -> HashKey {
-        HashKey {
-            value: self.value as u64,
-        }
+        HashKey::Integer(self.value)
     }
 }
 
 impl Hashable for Boolean {
     fn hash_key(&self) -> HashKey {
-        let value = if self.value { 1 } else { 0 };
-        HashKey { value }
+        HashKey::Boolean(self.value)
     }
 }
 
 impl Hashable for StringObj {
     fn hash_key(&self) -> HashKey {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-        let mut hasher = DefaultHasher::new();
-        self.value.hash(&mut hasher);
-        HashKey {
-            value: hasher.finish(),
-        }
+        HashKey::String(self.value.clone())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::box_it;
 
     #[test]
     fn test_string_hash_key() {
@@ -333,6 +454,47 @@ mod tests {
         assert_ne!(hello1.hash_key(), diff1.hash_key());
     }
 
+    #[test]
+    fn test_string_inspect() {
+        let string = StringObj {
+            value: "a\nb\t\"c\" \\".to_string(),
+        };
+        assert_eq!(string.inspect(), r#""a\nb\t\"c\" \\""#);
+    }
+
+    #[test]
+    fn test_hash_keys_depend_on_type() {
+        let one = Integer { value: 1 };
+        let yes = Boolean { value: true };
+        assert_ne!(one.hash_key(), yes.hash_key());
+    }
+
+    #[test]
+    fn test_maps_keep_their_seed() {
+        let key = Integer { value: 1 }.hash_key();
+        set_hash_seed(1);
+        let first = KeyedState::default();
+        set_hash_seed(2);
+        let second = KeyedState::default();
+        assert_ne!(first.hash_one(&key), second.hash_one(&key));
+
+        set_hash_seed(1);
+        let mut pairs = HashPairs::default();
+        let value: ObjectRef = box_it!(StringObj {
+            value: "one".to_string(),
+        });
+        pairs.insert(
+            key.clone(),
+            HashPair {
+                key: box_it!(Integer { value: 1 }),
+                value,
+            },
+        );
+        set_hash_seed(2);
+        assert_eq!(pairs.get(&key).unwrap().value.inspect(), "\"one\"");
+        assert_eq!(pairs.clone().get(&key).unwrap().value.inspect(), "\"one\"");
+    }
+
     #[test]
     fn test_boolean_hash_key() {
         let true1 = Boolean { value: true };
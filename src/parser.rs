@@ -1,17 +1,193 @@
-use crate::ast::Program;
-use lalrpop_util::lalrpop_mod;
+use crate::ast::{Expr, Program, Stmt};
+use lalrpop_util::{lalrpop_mod, lexer::Token, ParseError};
 
-lalrpop_mod!(grammar);
+lalrpop_mod!(
+    #[allow(clippy::all)]
+    grammar
+);
 
 pub fn parse_program(input: &str) -> Result<Program, String> {
-    grammar::ProgramParser::new()
+    let mut program = grammar::ProgramParser::new()
         .parse(input)
-        .map_err(|e| format!("{:?}", e))
+        .map_err(|e| format_parse_error(input, e))?;
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    for stmt in program.statements.iter_mut() {
+        set_stmt_lines(stmt, &line_starts);
+    }
+    Ok(program)
+}
+
+// Fills in the line of every span below `stmt`, given the offsets at which the input's lines
+// start.
+fn set_stmt_lines(stmt: &mut Stmt, line_starts: &[usize]) {
+    if let Some(span) = stmt.span_mut() {
+        span.line = line_starts.partition_point(|&start| start <= span.start);
+    }
+    match stmt {
+        Stmt::Let { value, .. }
+        | Stmt::LetDestructure { value, .. }
+        | Stmt::Const { value, .. }
+        | Stmt::Assign { value, .. } => set_expr_lines(value, line_starts),
+        Stmt::Return { return_value } => set_expr_lines(return_value, line_starts),
+        Stmt::Expr { expression } => set_expr_lines(expression, line_starts),
+        Stmt::Block { statements } => {
+            for stmt in statements {
+                set_stmt_lines(stmt, line_starts);
+            }
+        }
+        Stmt::While { condition, body } => {
+            set_expr_lines(condition, line_starts);
+            set_stmt_lines(body, line_starts);
+        }
+    }
+}
+
+fn set_expr_lines(expr: &mut Expr, line_starts: &[usize]) {
+    if let Some(span) = expr.span_mut() {
+        span.line = line_starts.partition_point(|&start| start <= span.start);
+    }
+    match expr {
+        Expr::Number(_) | Expr::Identifier(..) | Expr::Boolean(_) | Expr::StringLit(_) => {}
+        Expr::InfixOp { left, right, .. } => {
+            set_expr_lines(left, line_starts);
+            set_expr_lines(right, line_starts);
+        }
+        Expr::PrefixOp { right, .. } => set_expr_lines(right, line_starts),
+        Expr::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            set_expr_lines(condition, line_starts);
+            set_stmt_lines(consequence, line_starts);
+            if let Some(alt) = alternative {
+                set_stmt_lines(alt, line_starts);
+            }
+        }
+        Expr::With { bindings, body } => {
+            for (_, value) in bindings {
+                set_expr_lines(value, line_starts);
+            }
+            set_stmt_lines(body, line_starts);
+        }
+        Expr::Try { body, cleanup } => {
+            set_stmt_lines(body, line_starts);
+            set_stmt_lines(cleanup, line_starts);
+        }
+        Expr::LetIn { value, body, .. } => {
+            set_expr_lines(value, line_starts);
+            set_expr_lines(body, line_starts);
+        }
+        Expr::FuncLit { parameters, body } => {
+            for param in parameters {
+                set_expr_lines(param, line_starts);
+            }
+            set_stmt_lines(body, line_starts);
+        }
+        Expr::Call {
+            function,
+            arguments,
+            ..
+        } => {
+            set_expr_lines(function, line_starts);
+            for arg in arguments {
+                set_expr_lines(arg, line_starts);
+            }
+        }
+        Expr::ArrayLit { elements } => {
+            for e in elements {
+                set_expr_lines(e, line_starts);
+            }
+        }
+        Expr::Index { left, index, .. } => {
+            set_expr_lines(left, line_starts);
+            set_expr_lines(index, line_starts);
+        }
+        Expr::HashLit { pairs, .. } => {
+            for (k, v) in pairs {
+                set_expr_lines(k, line_starts);
+                set_expr_lines(v, line_starts);
+            }
+        }
+    }
+}
+
+fn format_parse_error(input: &str, error: ParseError<usize, Token, (usize, String)>) -> String {
+    let (offset, message) = match error {
+        ParseError::InvalidToken { location } => (location, "invalid token".to_string()),
+        ParseError::UnrecognizedEof { location, .. } => {
+            (location, "unexpected end of input".to_string())
+        }
+        // `:` only ever follows a hash key, so a pair that ends here is missing its value. At the
+        // start of a statement `{ a }` may just as well be a block missing a `;`.
+        ParseError::UnrecognizedToken {
+            token: (start, token, _),
+            expected,
+        } if (token.1 == "}" || token.1 == ",") && expected.iter().any(|e| e == "\":\"") => {
+            let message = if expected.iter().any(|e| e == "\";\"") {
+                format!("expected \":\" or \";\", got \"{}\"", token.1)
+            } else {
+                format!("expected \":\" after hash key, got \"{}\"", token.1)
+            };
+            (start, message)
+        }
+        ParseError::UnrecognizedToken {
+            token: (start, token, _),
+            ..
+        }
+        | ParseError::ExtraToken {
+            token: (start, token, _),
+        } => (start, format!("unexpected token \"{}\"", token.1)),
+        ParseError::User {
+            error: (offset, message),
+        } => (offset, message),
+    };
+    let (line, col) = line_col(input, offset);
+    format!("parse error at line {}, col {}: {}", line, col, message)
+}
+
+// 1-based line and column (in chars) of a byte offset into `input`.
+fn line_col(input: &str, offset: usize) -> (usize, usize) {
+    let before = &input[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+// Strips the surrounding double quotes from a string literal token and decodes its escape
+// sequences: `\n`, `\t`, `\r`, `\"` and `\\`.
+pub fn unquote(token: &str) -> Result<String, String> {
+    let mut value = String::new();
+    let mut chars = token[1..token.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('t') => value.push('\t'),
+            Some('r') => value.push('\r'),
+            Some('"') => value.push('"'),
+            Some('\\') => value.push('\\'),
+            Some(other) => {
+                return Err(format!(
+                    "unknown escape sequence `\\{}` in string literal",
+                    other
+                ))
+            }
+            None => return Err("unterminated escape sequence in string literal".to_string()),
+        }
+    }
+    Ok(value)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ast::{Expr, Span, Stmt};
 
     #[test]
     fn test_integer() {
@@ -53,6 +229,50 @@ mod tests {
 
         let expr = grammar::ExprParser::new().parse("\"hello world\"").unwrap();
         assert_eq!(format!("{:?}", expr), "\"hello world\"");
+
+        let expr = grammar::ExprParser::new().parse("\"hi\"").unwrap();
+        assert!(matches!(*expr, Expr::StringLit(ref s) if s == "hi"));
+
+        let expr = grammar::ExprParser::new()
+            .parse(r#""say \"hi\" \\o/""#)
+            .unwrap();
+        assert!(matches!(*expr, Expr::StringLit(ref s) if s == r#"say "hi" \o/"#));
+
+        let expr = grammar::ExprParser::new().parse(r#""a\nb\tc\r""#).unwrap();
+        assert!(matches!(*expr, Expr::StringLit(ref s) if s == "a\nb\tc\r"));
+        assert_eq!(format!("{:?}", expr), r#""a\nb\tc\r""#);
+    }
+
+    #[test]
+    fn test_unknown_string_escape() {
+        assert_eq!(
+            parse_program(r#"let s = "a\qb";"#).unwrap_err(),
+            "parse error at line 1, col 9: unknown escape sequence `\\q` in string literal"
+        );
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        assert_eq!(
+            parse_program("let = 5;").unwrap_err(),
+            "parse error at line 1, col 5: unexpected token \"=\""
+        );
+        assert_eq!(
+            parse_program("let a = 1;\nlet b = 2;\nif (a) { b; }}").unwrap_err(),
+            "parse error at line 3, col 14: unexpected token \"}\""
+        );
+        assert_eq!(
+            parse_program("let s = \"é\"; let = 1;").unwrap_err(),
+            "parse error at line 1, col 18: unexpected token \"=\""
+        );
+        assert_eq!(
+            parse_program("let a = 1").unwrap_err(),
+            "parse error at line 1, col 10: unexpected end of input"
+        );
+        assert_eq!(
+            parse_program("let a = 1 @ 2;").unwrap_err(),
+            "parse error at line 1, col 11: invalid token"
+        );
     }
 
     #[test]
@@ -94,6 +314,30 @@ mod tests {
         assert_eq!(format!("{:?}", expr), "(1 != 2)");
     }
 
+    #[test]
+    fn test_bitwise_expr() {
+        let expr = grammar::ExprParser::new().parse("1 << 4").unwrap();
+        assert_eq!(format!("{:?}", expr), "(1 << 4)");
+
+        let expr = grammar::ExprParser::new().parse("a >> 1 + 2").unwrap();
+        assert_eq!(format!("{:?}", expr), "(a >> (1 + 2))");
+
+        let expr = grammar::ExprParser::new().parse("1 << 2 < 3 >> 1").unwrap();
+        assert_eq!(format!("{:?}", expr), "((1 << 2) < (3 >> 1))");
+
+        let expr = grammar::ExprParser::new().parse("a & b == c").unwrap();
+        assert_eq!(format!("{:?}", expr), "(a & (b == c))");
+
+        let expr = grammar::ExprParser::new().parse("a | b ^ c & d").unwrap();
+        assert_eq!(format!("{:?}", expr), "(a | (b ^ (c & d)))");
+
+        let expr = grammar::ExprParser::new().parse("a & b | c ^ d").unwrap();
+        assert_eq!(format!("{:?}", expr), "((a & b) | (c ^ d))");
+
+        let expr = grammar::ExprParser::new().parse("a | b | c").unwrap();
+        assert_eq!(format!("{:?}", expr), "((a | b) | c)");
+    }
+
     #[test]
     fn test_prefix_expr() {
         let expr = grammar::ExprParser::new().parse("-1 + 2").unwrap();
@@ -121,6 +365,24 @@ mod tests {
         assert_eq!(format!("{:?}", expr), "(1 + (!2))");
     }
 
+    #[test]
+    fn test_comments() {
+        let plain = parse_program("let a = 1;\nlet b = a / 2;").unwrap();
+        let commented = parse_program(
+            "// setup\nlet a = 1; // one\n/* a block\n   comment */ let b = a /* inline */ / 2;\n// end",
+        )
+        .unwrap();
+        assert_eq!(format!("{:?}", commented), format!("{:?}", plain));
+
+        let program = parse_program("\"http://x\"; \"/* not a comment */\";").unwrap();
+        assert_eq!(
+            format!("{:?}", program),
+            "Program { statements: [\"http://x\", \"/* not a comment */\"] }"
+        );
+
+        assert!(parse_program("let a = 1; /* unterminated").is_err());
+    }
+
     #[test]
     fn test_if_expr() {
         let expr = grammar::ExprParser::new()
@@ -132,6 +394,24 @@ mod tests {
             .parse("if (true) { 1; } else { 2; }")
             .unwrap();
         assert_eq!(format!("{:?}", expr), "if (true) {\n  1\n} else {\n  2\n}");
+
+        let expr = grammar::ExprParser::new()
+            .parse("if (a) { 1; } else if (b) { 2; } else { 3; }")
+            .unwrap();
+        let expected = "if (a) {\n  1\n} else if (b) {\n  2\n} else {\n  3\n}";
+        assert_eq!(format!("{:?}", expr), expected);
+        let nested = grammar::ExprParser::new()
+            .parse("if (a) { 1; } else { if (b) { 2; } else { 3; }; }")
+            .unwrap();
+        assert_eq!(format!("{:?}", nested), expected);
+
+        let expr = grammar::ExprParser::new()
+            .parse("if (a) { 1; } else if (b) { 2; }")
+            .unwrap();
+        assert_eq!(
+            format!("{:?}", expr),
+            "if (a) {\n  1\n} else if (b) {\n  2\n}"
+        );
     }
 
     #[test]
@@ -251,6 +531,21 @@ mod tests {
 
         let expr = grammar::ExprParser::new().parse("add()").unwrap();
         assert_eq!(format!("{:?}", expr), "add()");
+
+        let expr = grammar::ExprParser::new().parse("fn(x){x;}(5)").unwrap();
+        assert_eq!(format!("{:?}", expr), "fn(x) {\n  x\n}(5)");
+
+        let expr = grammar::ExprParser::new().parse("getFn()(3)").unwrap();
+        assert_eq!(format!("{:?}", expr), "getFn()(3)");
+
+        let expr = grammar::ExprParser::new().parse("arr[0](1)").unwrap();
+        assert_eq!(format!("{:?}", expr), "(arr[0])(1)");
+
+        let expr = grammar::ExprParser::new().parse("(a + b)(1)").unwrap();
+        assert_eq!(format!("{:?}", expr), "(a + b)(1)");
+
+        let expr = grammar::ExprParser::new().parse("-f(1) * 2").unwrap();
+        assert_eq!(format!("{:?}", expr), "((-f(1)) * 2)");
     }
 
     #[test]
@@ -269,6 +564,17 @@ mod tests {
 
         let expr = grammar::ExprParser::new().parse("[1 + 2, 3 * 4]").unwrap();
         assert_eq!(format!("{:?}", expr), "[(1 + 2), (3 * 4)]");
+
+        let expr = grammar::ExprParser::new().parse("[1, 2,]").unwrap();
+        assert_eq!(format!("{:?}", expr), "[1, 2]");
+
+        let expr = grammar::ExprParser::new()
+            .parse("[\n  1,\n  [2,\n   3,],\n\n]")
+            .unwrap();
+        assert_eq!(format!("{:?}", expr), "[1, [2, 3]]");
+
+        assert!(grammar::ExprParser::new().parse("[,]").is_err());
+        assert!(grammar::ExprParser::new().parse("[1,,]").is_err());
     }
 
     #[test]
@@ -295,6 +601,23 @@ mod tests {
 
         let expr = grammar::ExprParser::new().parse("[0,2][0]").unwrap();
         assert_eq!(format!("{:?}", expr), "([0, 2][0])");
+
+        let expr = grammar::ExprParser::new().parse("a[0][1]").unwrap();
+        assert_eq!(format!("{:?}", expr), "((a[0])[1])");
+
+        let expr = grammar::ExprParser::new().parse("f(1)[2]").unwrap();
+        assert_eq!(format!("{:?}", expr), "(f(1)[2])");
+
+        let expr = grammar::ExprParser::new().parse("-a[0]").unwrap();
+        assert_eq!(format!("{:?}", expr), "(-(a[0]))");
+
+        let expr = grammar::ExprParser::new().parse("h[\"key\"]").unwrap();
+        assert_eq!(format!("{:?}", expr), "(h[\"key\"])");
+
+        let expr = grammar::ExprParser::new()
+            .parse("{\"key\": 1}[\"key\"]")
+            .unwrap();
+        assert_eq!(format!("{:?}", expr), "({\"key\": 1, }[\"key\"])");
     }
 
     #[test]
@@ -331,6 +654,52 @@ mod tests {
             format!("{:?}", expr),
             "{\"one\": (1 + 2), \"two\": (3 * 4), \"three\": (5 - 6), }"
         );
+
+        let expr = grammar::ExprParser::new()
+            .parse("{\n  \"one\": 1,\n  \"two\":\n    [2, 2],\n\n  \"three\": {3: 3,},\n}")
+            .unwrap();
+        assert_eq!(
+            format!("{:?}", expr),
+            "{\"one\": 1, \"two\": [2, 2], \"three\": {3: 3, }, }"
+        );
+    }
+
+    #[test]
+    fn test_hash_literal_missing_value() {
+        let tests = vec![
+            (
+                "{\"a\"};",
+                "parse error at line 1, col 5: expected \":\" or \";\", got \"}\"",
+            ),
+            (
+                "let a = 1;\n{ a }",
+                "parse error at line 2, col 5: expected \":\" or \";\", got \"}\"",
+            ),
+            (
+                "let h = {\"a\"};",
+                "parse error at line 1, col 13: expected \":\" after hash key, got \"}\"",
+            ),
+            (
+                "let h = {\n  \"a\": 1,\n  \"b\"\n};",
+                "parse error at line 4, col 1: expected \":\" after hash key, got \"}\"",
+            ),
+            (
+                "let h = {\"a\", \"b\": 1};",
+                "parse error at line 1, col 13: expected \":\" after hash key, got \",\"",
+            ),
+            (
+                "let h = {\"a\": };",
+                "parse error at line 1, col 15: unexpected token \"}\"",
+            ),
+            (
+                "let h = {,};",
+                "parse error at line 1, col 10: unexpected token \",\"",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse_program(input).unwrap_err(), expected, "{}", input);
+        }
     }
 
     #[test]
@@ -382,6 +751,29 @@ mod tests {
         assert_eq!(format!("{:?}", stmt), "let a = (((1 * 2) * 3) * 4)");
     }
 
+    #[test]
+    fn test_const_stmt() {
+        let stmt = grammar::StmtParser::new().parse("const PI = 3;").unwrap();
+        assert_eq!(format!("{:?}", stmt), "const PI = 3");
+
+        let stmt = grammar::StmtParser::new()
+            .parse("const a = 1 + 2 * 3;")
+            .unwrap();
+        assert_eq!(format!("{:?}", stmt), "const a = (1 + (2 * 3))");
+    }
+
+    #[test]
+    fn test_assign_stmt() {
+        let stmt = grammar::StmtParser::new().parse("a = 1;").unwrap();
+        assert_eq!(format!("{:?}", stmt), "a = 1");
+
+        let stmt = grammar::StmtParser::new().parse("a = a + 1;").unwrap();
+        assert_eq!(format!("{:?}", stmt), "a = (a + 1)");
+
+        let stmt = grammar::StmtParser::new().parse("a == 1;").unwrap();
+        assert_eq!(format!("{:?}", stmt), "(a == 1)");
+    }
+
     #[test]
     fn test_return_stmt() {
         let stmt = grammar::StmtParser::new().parse("return 1;").unwrap();
@@ -467,6 +859,112 @@ mod tests {
         assert_eq!(format!("{:?}", stmt), "{\n  1\n  2\n  3\n}");
     }
 
+    #[test]
+    fn test_while_stmt() {
+        let stmt = grammar::StmtParser::new()
+            .parse("while (x < 10) { x; }")
+            .unwrap();
+        assert_eq!(format!("{:?}", stmt), "while ((x < 10)) {\n  x\n}");
+
+        let stmt = grammar::StmtParser::new()
+            .parse("while (true) { let a = a + 1; a; }")
+            .unwrap();
+        assert_eq!(
+            format!("{:?}", stmt),
+            "while (true) {\n  let a = (a + 1)\n  a\n}"
+        );
+    }
+
+    #[test]
+    fn test_with_expr() {
+        let expr = grammar::ExprParser::new()
+            .parse("with (x = 1, y = 2) { x + y; }")
+            .unwrap();
+        assert_eq!(format!("{:?}", expr), "with (x = 1, y = 2) {\n  (x + y)\n}");
+
+        let expr = grammar::ExprParser::new().parse("with () { 1; }").unwrap();
+        assert_eq!(format!("{:?}", expr), "with () {\n  1\n}");
+    }
+
+    #[test]
+    fn test_let_in_expr() {
+        let expr = grammar::ExprParser::new()
+            .parse("let x = 5 in x * 2")
+            .unwrap();
+        assert_eq!(format!("{:?}", expr), "let x = 5 in (x * 2)");
+
+        let expr = grammar::ExprParser::new()
+            .parse("let x = 1 in let y = 2 in x + y")
+            .unwrap();
+        assert_eq!(format!("{:?}", expr), "let x = 1 in let y = 2 in (x + y)");
+    }
+
+    #[test]
+    fn test_try_expr() {
+        let expr = grammar::ExprParser::new()
+            .parse("try { f(); } finally { g(); }")
+            .unwrap();
+        assert_eq!(format!("{:?}", expr), "try {\n  f()\n} finally {\n  g()\n}");
+    }
+
+    #[test]
+    fn test_let_destructure_stmt() {
+        let stmt = grammar::StmtParser::new()
+            .parse("let [a, b, _] = [1, 2, 3];")
+            .unwrap();
+        assert_eq!(format!("{:?}", stmt), "let [a, b, _] = [1, 2, 3]");
+
+        let stmt = grammar::StmtParser::new().parse("let [] = f();").unwrap();
+        assert_eq!(format!("{:?}", stmt), "let [] = f()");
+    }
+
+    #[test]
+    fn test_spans() {
+        let program = parse_program("let a = 1;\n  b + a;").unwrap();
+        let stmt = &program.statements[0];
+        assert_eq!(
+            stmt.span(),
+            Some(Span {
+                start: 0,
+                end: 10,
+                line: 1
+            })
+        );
+        match *program.statements[1] {
+            Stmt::Expr { ref expression } => assert_eq!(
+                expression.span(),
+                Some(Span {
+                    start: 13,
+                    end: 18,
+                    line: 2
+                })
+            ),
+            _ => panic!("Expected expression statement"),
+        }
+
+        let program = parse_program("let f = fn(x) {\n\n  x[0];\n};").unwrap();
+        match *program.statements[0] {
+            Stmt::Let { ref value, .. } => match **value {
+                Expr::FuncLit { ref body, .. } => match **body {
+                    Stmt::Block { ref statements } => match *statements[0] {
+                        Stmt::Expr { ref expression } => {
+                            assert_eq!(expression.span().unwrap().line, 3)
+                        }
+                        _ => panic!("Expected expression statement"),
+                    },
+                    _ => panic!("Expected block statement"),
+                },
+                _ => panic!("Expected function literal"),
+            },
+            _ => panic!("Expected let statement"),
+        }
+
+        // Lines are looked up once the input is parsed, so long inputs parse in linear time.
+        let input = "let a = 1;\n".repeat(20000);
+        let program = parse_program(&input).unwrap();
+        assert_eq!(program.statements[19999].span().unwrap().line, 20000);
+    }
+
     #[test]
     fn test_program() {
         let program = grammar::ProgramParser::new().parse("1;").unwrap();
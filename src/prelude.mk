@@ -0,0 +1,8 @@
+let identity = fn(x) { x; };
+let compose = fn(f, g) { fn(x) { f(g(x)); }; };
+let sum = fn(arr) { reduce(arr, 0, fn(acc, x) { acc + x; }); };
+let product = fn(arr) { reduce(arr, 1, fn(acc, x) { acc * x; }); };
+let flat_map = fn(arr, f) {
+  reduce(arr, [], fn(acc, x) { reduce(f(x), acc, fn(out, y) { push(out, y); }); });
+};
+let find = fn(arr, pred) { first(filter(arr, pred)); };
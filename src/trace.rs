@@ -0,0 +1,82 @@
+use crate::ast::Expr;
+use crate::evaluator::EvalObserver;
+use crate::object::ObjectRef;
+use std::cell::RefCell;
+
+/// Records every evaluated expression with the value it produced, in evaluation order.
+#[derive(Default)]
+pub struct Trace {
+    steps: RefCell<Vec<(String, String)>>,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Trace::default()
+    }
+
+    /// Returns the recorded `(expression, value)` pairs and starts over.
+    pub fn take(&self) -> Vec<(String, String)> {
+        self.steps.take()
+    }
+}
+
+impl EvalObserver for Trace {
+    fn on_expr(&self, expr: &Expr, value: &ObjectRef) {
+        self.steps
+            .borrow_mut()
+            .push((format!("{:?}", expr), value.inspect()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::Environment;
+    use crate::evaluator::eval_program;
+    use crate::parser::parse_program;
+    use std::rc::Rc;
+
+    fn steps(steps: &[(&str, &str)]) -> Vec<(String, String)> {
+        steps
+            .iter()
+            .map(|(e, v)| (e.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_trace_order() {
+        let trace = Rc::new(Trace::new());
+        let mut env = Environment::new();
+        env.set_observer(Some(trace.clone()));
+
+        let program = parse_program("1 + 2 * 3;").unwrap();
+        eval_program(&program, &mut env).unwrap();
+        assert_eq!(
+            trace.take(),
+            steps(&[
+                ("1", "1"),
+                ("2", "2"),
+                ("3", "3"),
+                ("(2 * 3)", "6"),
+                ("(1 + (2 * 3))", "7"),
+            ])
+        );
+
+        env.set_observer(None);
+        eval_program(&program, &mut env).unwrap();
+        assert!(trace.take().is_empty());
+    }
+
+    #[test]
+    fn test_trace_reaches_existing_closures() {
+        let mut env = Environment::new();
+        let program = parse_program("let double = fn(x) { x * 2; };").unwrap();
+        eval_program(&program, &mut env).unwrap();
+
+        let trace = Rc::new(Trace::new());
+        env.set_observer(Some(trace.clone()));
+        let program = parse_program("double(4);").unwrap();
+        eval_program(&program, &mut env).unwrap();
+        assert!(trace.take().contains(&steps(&[("(x * 2)", "8")])[0]));
+    }
+}
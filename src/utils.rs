@@ -1,7 +1,7 @@
 #[macro_export]
 macro_rules! box_it {
     ($e: expr) => {
-        Box::new($e)
+        std::rc::Rc::new($e)
     };
 }
 
@@ -0,0 +1,163 @@
+use crate::ast::{Expr, Program, Stmt};
+
+pub trait Visitor {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+}
+
+pub fn walk<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for stmt in &program.statements {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Stmt) {
+    match stmt {
+        Stmt::Let { ref value, .. }
+        | Stmt::LetDestructure { ref value, .. }
+        | Stmt::Const { ref value, .. }
+        | Stmt::Assign { ref value, .. } => visitor.visit_expr(value),
+        Stmt::Return { ref return_value } => visitor.visit_expr(return_value),
+        Stmt::Expr { ref expression } => visitor.visit_expr(expression),
+        Stmt::Block { ref statements } => {
+            for stmt in statements {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        Stmt::While {
+            ref condition,
+            ref body,
+        } => {
+            visitor.visit_expr(condition);
+            visitor.visit_stmt(body);
+        }
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Number(_) | Expr::Identifier(..) | Expr::Boolean(_) | Expr::StringLit(_) => {}
+        Expr::InfixOp {
+            ref left,
+            ref right,
+            ..
+        } => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        Expr::PrefixOp { ref right, .. } => visitor.visit_expr(right),
+        Expr::If {
+            ref condition,
+            ref consequence,
+            ref alternative,
+        } => {
+            visitor.visit_expr(condition);
+            visitor.visit_stmt(consequence);
+            if let Some(alt) = alternative {
+                visitor.visit_stmt(alt);
+            }
+        }
+        Expr::With {
+            ref bindings,
+            ref body,
+        } => {
+            for (_, value) in bindings {
+                visitor.visit_expr(value);
+            }
+            visitor.visit_stmt(body);
+        }
+        Expr::Try {
+            ref body,
+            ref cleanup,
+        } => {
+            visitor.visit_stmt(body);
+            visitor.visit_stmt(cleanup);
+        }
+        Expr::LetIn {
+            ref value,
+            ref body,
+            ..
+        } => {
+            visitor.visit_expr(value);
+            visitor.visit_expr(body);
+        }
+        Expr::FuncLit {
+            ref parameters,
+            ref body,
+        } => {
+            for param in parameters {
+                visitor.visit_expr(param);
+            }
+            visitor.visit_stmt(body);
+        }
+        Expr::Call {
+            ref function,
+            ref arguments,
+            ..
+        } => {
+            visitor.visit_expr(function);
+            for arg in arguments {
+                visitor.visit_expr(arg);
+            }
+        }
+        Expr::ArrayLit { ref elements } => {
+            for e in elements {
+                visitor.visit_expr(e);
+            }
+        }
+        Expr::Index {
+            ref left,
+            ref index,
+            ..
+        } => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(index);
+        }
+        Expr::HashLit { ref pairs, .. } => {
+            for (k, v) in pairs {
+                visitor.visit_expr(k);
+                visitor.visit_expr(v);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    struct IdentifierCollector {
+        names: Vec<String>,
+    }
+
+    impl Visitor for IdentifierCollector {
+        fn visit_expr(&mut self, expr: &Expr) {
+            if let Expr::Identifier(name, _) = expr {
+                self.names.push(name.clone());
+            }
+            walk_expr(self, expr);
+        }
+    }
+
+    #[test]
+    fn test_collect_identifiers() {
+        let input = "
+        let add = fn(x, y) { x + y; };
+        let result = add(a, b[0]);
+        if (result > c) { return {d: e}; } else { [f, -g]; };
+        ";
+        let program = parse_program(input).unwrap();
+        let mut collector = IdentifierCollector { names: Vec::new() };
+        walk(&mut collector, &program);
+        assert_eq!(
+            collector.names,
+            vec!["x", "y", "x", "y", "add", "a", "b", "result", "c", "d", "e", "f", "g"]
+        );
+    }
+}
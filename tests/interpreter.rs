@@ -0,0 +1,30 @@
+use monkey_rs::interpreter::Interpreter;
+
+#[test]
+fn test_run_keeps_state_between_calls() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .run("let greet = fn(name) { \"Hello, \" + name; };")
+        .unwrap();
+    interpreter.run("let name = \"Monkey\";").unwrap();
+    assert_eq!(
+        interpreter.run("greet(name);").unwrap().inspect(),
+        "\"Hello, Monkey\""
+    );
+
+    interpreter.reset();
+    assert_eq!(
+        interpreter.run("greet(name);").unwrap().inspect(),
+        "identifier not found: greet (line 1)"
+    );
+}
+
+#[test]
+fn test_hash_inspect_is_reproducible() {
+    let src = "let h = {\"b\": 1, 2: true, \"a\": [1], false: \"x\"}; \
+               filter_hash(h, fn(k, v) { type(k) != \"INTEGER\"; });";
+    let first = Interpreter::new().run(src).unwrap().inspect();
+    let second = Interpreter::new().run(src).unwrap().inspect();
+    assert_eq!(first, second);
+    assert_eq!(first, "{\"b\": 1, \"a\": [1], false: \"x\"}");
+}